@@ -0,0 +1,70 @@
+mod common;
+
+use common::xorshift;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use range_minimum_query::Rmq;
+
+fn queries(len: usize, count: usize, max_width: usize, seed: u64) -> Vec<std::ops::Range<usize>> {
+    let mut state = seed;
+    (0..count)
+        .map(|_| {
+            let start = xorshift(&mut state) as usize % len;
+            let width = 1 + xorshift(&mut state) as usize % max_width;
+            start..(start + width).min(len)
+        })
+        .collect()
+}
+
+fn batch(c: &mut Criterion) {
+    let len = 10_000_000;
+    let mut state = 42;
+    let rmq: Rmq = (0..len).map(|_| xorshift(&mut state) as u32).collect();
+
+    let mut group = c.benchmark_group("range_minimum_batch");
+    for max_width in [16, 1024, len] {
+        let queries = queries(len, 1_000_000, max_width, 7);
+        let mut out = vec![0; queries.len()];
+        group.throughput(Throughput::Elements(queries.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("single", max_width),
+            &queries,
+            |b, queries| {
+                b.iter(|| {
+                    for (query, pos) in queries.iter().zip(out.iter_mut()) {
+                        *pos = rmq.range_minimum(query.clone()).unwrap();
+                    }
+                    black_box(&out);
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("batch", max_width),
+            &queries,
+            |b, queries| {
+                b.iter(|| {
+                    rmq.range_minimum_batch(queries, &mut out).unwrap();
+                    black_box(&out);
+                })
+            },
+        );
+        #[cfg(feature = "rayon")]
+        group.bench_with_input(
+            BenchmarkId::new("par_batch", max_width),
+            &queries,
+            |b, queries| {
+                b.iter(|| {
+                    rmq.par_range_minimum_batch(queries, &mut out).unwrap();
+                    black_box(&out);
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = batch
+}
+criterion_main!(benches);
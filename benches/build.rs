@@ -0,0 +1,48 @@
+mod common;
+
+use common::xorshift;
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use range_minimum_query::{layout, Rmq};
+
+fn build(c: &mut Criterion) {
+    let len = 10_000_000;
+    let mut state = 42;
+    let values: Vec<u32> = (0..len).map(|_| xorshift(&mut state) as u32).collect();
+
+    let mut group = c.benchmark_group("build");
+    group.throughput(Throughput::Elements(len as u64));
+    group.bench_with_input(BenchmarkId::new("from_iter", len), &values, |b, values| {
+        b.iter(|| black_box(Rmq::from_iter(values)))
+    });
+    // the same sequence written from the back, without reversing it afterwards
+    group.bench_with_input(
+        BenchmarkId::new("from_exact_size_iter", len),
+        &values,
+        |b, values| b.iter(|| black_box(Rmq::from_exact_size_iter(values))),
+    );
+    group.bench_with_input(BenchmarkId::new("from_slice", len), &values, |b, values| {
+        b.iter(|| black_box(Rmq::from_slice(values)))
+    });
+    // converting the layout only rebuilds the rank, select and excess structures
+    group.bench_with_input(
+        BenchmarkId::new("auxiliary_structures", len),
+        &values,
+        |b, values| {
+            b.iter_batched(
+                || Rmq::from_iter(values),
+                |rmq| black_box(rmq.into_layout::<layout::Balanced>()),
+                BatchSize::PerIteration,
+            )
+        },
+    );
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = build
+}
+criterion_main!(benches);
@@ -0,0 +1,7 @@
+/// deterministic pseudo random numbers so runs are comparable
+pub fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
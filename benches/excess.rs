@@ -0,0 +1,52 @@
+mod common;
+
+use common::xorshift;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use range_minimum_query::{ExcessBackend, Rmq};
+
+fn excess_backends(c: &mut Criterion) {
+    let len = 1_000_000;
+    let mut state = 42;
+    let inputs: [(&str, Vec<u64>); 3] = [
+        ("random", (0..len).map(|_| xorshift(&mut state)).collect()),
+        // a single path, every range minimum is searched within long runs of ones
+        ("increasing", (0..len as u64).collect()),
+        // minima at the far ends of the ranges force scans of partial blocks
+        ("sawtooth", (0..len as u64).map(|i| i % 1000).collect()),
+    ];
+    let queries: Vec<_> = (0..10_000)
+        .map(|_| {
+            let start = xorshift(&mut state) as usize % len;
+            let end = start + xorshift(&mut state) as usize % (len - start);
+            start..=end
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("excess_backend");
+    for (name, values) in &inputs {
+        let rmq: Rmq = values.iter().collect();
+        for backend in [
+            ExcessBackend::Lookup,
+            ExcessBackend::Swar,
+            ExcessBackend::Sse41,
+            ExcessBackend::Avx2,
+        ] {
+            if !backend.is_supported() {
+                continue;
+            }
+            backend.force();
+            group.bench_function(BenchmarkId::new(*name, format!("{backend:?}")), |b| {
+                b.iter(|| {
+                    for query in &queries {
+                        black_box(rmq.range_minimum(query.clone()));
+                    }
+                })
+            });
+        }
+    }
+    group.finish();
+    ExcessBackend::detect().force();
+}
+
+criterion_group!(benches, excess_backends);
+criterion_main!(benches);
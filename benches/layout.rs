@@ -0,0 +1,63 @@
+mod common;
+
+use common::xorshift;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use range_minimum_query::layout::{self, Layout};
+use range_minimum_query::Rmq;
+
+fn queries(len: usize, count: usize, seed: u64) -> Vec<std::ops::Range<usize>> {
+    let mut state = seed;
+    (0..count)
+        .map(|_| {
+            let a = xorshift(&mut state) as usize % len;
+            let b = xorshift(&mut state) as usize % len;
+            a.min(b)..a.max(b) + 1
+        })
+        .collect()
+}
+
+fn bench_layout<L: Layout>(
+    c: &mut Criterion,
+    name: &str,
+    values: &[u32],
+    queries: &[std::ops::Range<usize>],
+) {
+    let rmq: Rmq<L> = Rmq::from_iter(values).into_layout();
+    println!(
+        "{name}: {:.3} bits per element",
+        rmq.space_breakdown().bits_per_element()
+    );
+
+    let mut group = c.benchmark_group("layout");
+    group.throughput(Throughput::Elements(queries.len() as u64));
+    group.bench_with_input(BenchmarkId::new("query", name), queries, |b, queries| {
+        b.iter(|| {
+            for query in queries {
+                black_box(rmq.range_minimum(query.clone()));
+            }
+        })
+    });
+    group.throughput(Throughput::Elements(values.len() as u64));
+    group.bench_with_input(BenchmarkId::new("build", name), values, |b, values| {
+        b.iter(|| black_box(Rmq::from_iter(values).into_layout::<L>()))
+    });
+    group.finish();
+}
+
+fn layouts(c: &mut Criterion) {
+    let len = 10_000_000;
+    let mut state = 42;
+    let values: Vec<u32> = (0..len).map(|_| xorshift(&mut state) as u32).collect();
+    let queries = queries(len, 1_000_000, 7);
+
+    bench_layout::<layout::Compact>(c, "Compact", &values, &queries);
+    bench_layout::<layout::Balanced>(c, "Balanced", &values, &queries);
+    bench_layout::<layout::Fast>(c, "Fast", &values, &queries);
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = layouts
+}
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+mod common;
+
+use common::xorshift;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use range_minimum_query::{Rmq, SelectBackend};
+
+fn select_backends(c: &mut Criterion) {
+    let len = 1_000_000;
+    let mut state = 42;
+    let rmq: Rmq = (0..len).map(|_| xorshift(&mut state) as u32).collect();
+    let queries: Vec<_> = (0..10_000)
+        .map(|_| {
+            let start = xorshift(&mut state) as usize % len;
+            let end = start + xorshift(&mut state) as usize % (len - start);
+            start..=end
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("select_backend");
+    for backend in [SelectBackend::Broadword, SelectBackend::Bmi2] {
+        if !backend.is_supported() {
+            continue;
+        }
+        backend.force();
+        group.bench_function(
+            BenchmarkId::new("range_minimum", format!("{backend:?}")),
+            |b| {
+                b.iter(|| {
+                    for query in &queries {
+                        black_box(rmq.range_minimum(query.clone()));
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+    SelectBackend::detect().force();
+}
+
+criterion_group!(benches, select_backends);
+criterion_main!(benches);
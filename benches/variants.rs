@@ -0,0 +1,59 @@
+mod common;
+
+use common::xorshift;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use range_minimum_query::{BlockRmq, DynamicRmq, RangeMinimum, Rmq, SparseTableRmq};
+
+fn queries(len: usize, count: usize, seed: u64) -> Vec<std::ops::Range<usize>> {
+    let mut state = seed;
+    (0..count)
+        .map(|_| {
+            let a = xorshift(&mut state) as usize % len;
+            let b = xorshift(&mut state) as usize % len;
+            a.min(b)..a.max(b) + 1
+        })
+        .collect()
+}
+
+fn bench_queries(
+    group: &mut criterion::BenchmarkGroup<'_, criterion::measurement::WallTime>,
+    name: &str,
+    len: usize,
+    rmq: &impl RangeMinimum,
+    queries: &[std::ops::Range<usize>],
+) {
+    group.bench_with_input(BenchmarkId::new(name, len), queries, |b, queries| {
+        b.iter(|| {
+            for query in queries {
+                black_box(rmq.range_minimum(query.clone()));
+            }
+        })
+    });
+}
+
+fn variants(c: &mut Criterion) {
+    let mut group = c.benchmark_group("range_minimum");
+    for len in [1_000, 1_000_000] {
+        let mut state = 42;
+        let values: Vec<u32> = (0..len).map(|_| xorshift(&mut state) as u32).collect();
+        let queries = queries(len, 100_000, 7);
+        group.throughput(Throughput::Elements(queries.len() as u64));
+
+        let rmq = Rmq::from_iter(&values);
+        bench_queries(&mut group, "Rmq", len, &rmq, &queries);
+        let sparse_table = SparseTableRmq::new(values.clone());
+        bench_queries(&mut group, "SparseTableRmq", len, &sparse_table, &queries);
+        let block = BlockRmq::new(values.clone());
+        bench_queries(&mut group, "BlockRmq", len, &block, &queries);
+        let dynamic = DynamicRmq::from_iter(values);
+        bench_queries(&mut group, "DynamicRmq", len, &dynamic, &queries);
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = variants
+}
+criterion_main!(benches);
@@ -0,0 +1,203 @@
+use crate::bp::{AsBpRef, BitVec64, BpBitVec, BpRef};
+
+/// A balanced parentheses sequence supporting succinct ordinal tree navigation
+///
+/// Every node of a tree is represented by an opening parenthesis (`true`)
+/// followed by the parentheses of its children and a closing parenthesis
+/// (`false`). Navigation uses the same rank, select and excess search
+/// structures as [`Rmq`](crate::Rmq), about `2.7` bits per parenthesis.
+///
+/// ```rust
+/// use range_minimum_query::BalancedParentheses;
+///
+/// // ( ( ) ( ( ) ) )
+/// let bp = BalancedParentheses::new([true, true, false, true, true, false, false, false]).unwrap();
+/// assert_eq!(bp.find_close(0), Some(7));
+/// assert_eq!(bp.find_open(6), Some(3));
+/// assert_eq!(bp.enclose(4), Some(3));
+/// assert_eq!(bp.depth(4), Some(2));
+/// assert_eq!(bp.subtree_size(3), Some(2));
+/// assert_eq!(bp.select_open(2), Some(3));
+/// ```
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BalancedParentheses {
+    bp: BpBitVec,
+}
+
+impl BalancedParentheses {
+    /// builds the sequence from `bits`, where `true` is an opening parenthesis
+    ///
+    /// returns `None` if the parentheses are not balanced
+    pub fn new(bits: impl IntoIterator<Item = bool>) -> Option<Self> {
+        let mut bv = BitVec64::new();
+        let mut excess = 0isize;
+        for bit in bits {
+            excess += if bit { 1 } else { -1 };
+            if excess < 0 {
+                return None;
+            }
+            bv.push(bit);
+        }
+        if excess != 0 {
+            return None;
+        }
+        Some(Self {
+            bp: BpBitVec::from_bitvec(bv),
+        })
+    }
+
+    /// returns the number of parentheses
+    pub fn len(&self) -> usize {
+        self.bp().len()
+    }
+
+    /// returns `true` if the sequence does not contain any parentheses
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// returns `true` if position `i` holds an opening parenthesis
+    pub fn is_open(&self, i: usize) -> bool {
+        i < self.len() && self.bp().bit(i)
+    }
+
+    /// returns the number of opening parentheses before position `i`
+    pub fn rank_open(&self, i: usize) -> usize {
+        self.bp().rank1(i.min(self.len()) as u64) as usize
+    }
+
+    /// returns the number of closing parentheses before position `i`
+    pub fn rank_close(&self, i: usize) -> usize {
+        i.min(self.len()) - self.rank_open(i)
+    }
+
+    /// returns the position of the `k`-th opening parenthesis, counting from zero
+    pub fn select_open(&self, k: usize) -> Option<usize> {
+        (k < self.len() / 2).then(|| self.bp().select1(k))
+    }
+
+    /// returns the position of the `k`-th closing parenthesis, counting from zero
+    pub fn select_close(&self, k: usize) -> Option<usize> {
+        (k < self.len() / 2).then(|| self.bp().select0(k))
+    }
+
+    /// returns the position of the closing parenthesis matching the opening one at `i`
+    pub fn find_close(&self, i: usize) -> Option<usize> {
+        if !self.is_open(i) {
+            return None;
+        }
+        let bp = self.bp();
+        bp.fwd_search(i + 1, bp.excess(i)).map(|p| p - 1)
+    }
+
+    /// returns the position of the opening parenthesis matching the closing one at `i`
+    pub fn find_open(&self, i: usize) -> Option<usize> {
+        if i >= self.len() || self.is_open(i) {
+            return None;
+        }
+        let bp = self.bp();
+        bp.bwd_search(i, bp.excess(i + 1))
+    }
+
+    /// returns the position of the opening parenthesis of the parent
+    /// of the node opened at `i`
+    pub fn enclose(&self, i: usize) -> Option<usize> {
+        if !self.is_open(i) {
+            return None;
+        }
+        let bp = self.bp();
+        bp.bwd_search(i, bp.excess(i) - 1)
+    }
+
+    /// returns the number of proper ancestors of the node whose
+    /// opening or closing parenthesis is at `i`
+    pub fn depth(&self, i: usize) -> Option<usize> {
+        if i >= self.len() {
+            return None;
+        }
+        let offset = if self.is_open(i) { i } else { i + 1 };
+        Some(self.bp().excess(offset) as usize)
+    }
+
+    /// returns the number of nodes in the subtree of the node opened at `i`,
+    /// including the node itself
+    pub fn subtree_size(&self, i: usize) -> Option<usize> {
+        self.find_close(i).map(|close| (close + 1 - i) / 2)
+    }
+
+    fn bp(&self) -> BpRef<'_> {
+        self.bp.as_bp_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::BalancedParentheses;
+    use crate::bp::balanced;
+
+    fn naive_find_close(bits: &[bool], i: usize) -> usize {
+        let mut excess = 0;
+        for (j, &bit) in bits.iter().enumerate().skip(i) {
+            excess += if bit { 1 } else { -1 };
+            if excess == 0 {
+                return j;
+            }
+        }
+        unreachable!()
+    }
+
+    proptest! {
+        #[test]
+        fn navigation_matches_naive(
+            steps in prop::collection::vec(any::<bool>(), 1..3000)
+        ) {
+            let bits: Vec<bool> = balanced(&steps).into_iter().collect();
+            let bp = BalancedParentheses::new(bits.iter().copied()).unwrap();
+            prop_assert_eq!(bp.len(), bits.len());
+
+            let mut stack = Vec::new();
+            let mut opens = 0;
+            let mut closes = 0;
+            for (i, &bit) in bits.iter().enumerate() {
+                prop_assert_eq!(bp.is_open(i), bit);
+                prop_assert_eq!(bp.rank_open(i), opens);
+                prop_assert_eq!(bp.rank_close(i), closes);
+                if bit {
+                    let close = naive_find_close(&bits, i);
+                    prop_assert_eq!(bp.select_open(opens), Some(i));
+                    prop_assert_eq!(bp.find_close(i), Some(close));
+                    prop_assert_eq!(bp.enclose(i), stack.last().copied());
+                    prop_assert_eq!(bp.depth(i), Some(stack.len()));
+                    prop_assert_eq!(bp.subtree_size(i), Some(bits[i..=close].iter().filter(|b| **b).count()));
+                    prop_assert_eq!(bp.find_open(i), None);
+                    stack.push(i);
+                    opens += 1;
+                } else {
+                    let open = stack.pop().unwrap();
+                    prop_assert_eq!(bp.select_close(closes), Some(i));
+                    prop_assert_eq!(bp.find_open(i), Some(open));
+                    prop_assert_eq!(bp.depth(i), Some(stack.len()));
+                    prop_assert_eq!(bp.find_close(i), None);
+                    closes += 1;
+                }
+            }
+            prop_assert_eq!(bp.select_open(opens), None);
+            prop_assert_eq!(bp.rank_open(bits.len()), opens);
+        }
+    }
+
+    #[test]
+    fn unbalanced_and_empty_sequences() {
+        assert!(BalancedParentheses::new([false, true]).is_none());
+        assert!(BalancedParentheses::new([true, true, false]).is_none());
+
+        let empty = BalancedParentheses::new([]).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.find_close(0), None);
+        assert_eq!(empty.select_open(0), None);
+        assert_eq!(empty.depth(0), None);
+    }
+}
@@ -0,0 +1,173 @@
+use alloc::vec::Vec;
+
+use crate::range_minimum::{resolve_range, RangeMinimum};
+use crate::sparse_table::MinTable;
+use crate::RmqError;
+
+/// number of elements per block, one bit of a mask each
+const BLOCK_SIZE: usize = 64;
+
+/// A block decomposition answering range minimum queries in constant time
+/// with one word per element
+///
+/// The elements are split into blocks of 64, in the style of Fischer and Heun.
+/// The minima of the blocks are indexed by a sparse table, which takes
+/// `n / 64 * log n` words. Within a block, every position stores a bit mask of
+/// the elements left on the stack of the Cartesian tree construction, so a query
+/// inside a block is answered by masking and counting trailing zeros.
+///
+/// If the minimum of a range occurs more than once, the leftmost position is reported.
+///
+/// ```rust
+/// use range_minimum_query::BlockRmq;
+///
+/// let rmq = BlockRmq::new(vec![0,5,2,5,4,3,1,6,3]);
+/// assert_eq!(rmq.range_minimum(2..=7),Some(6));
+/// assert_eq!(rmq.values()[6],1);
+/// ```
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockRmq<T> {
+    values: Vec<T>,
+    /// the positions of the block left on the stack after every element
+    stack_masks: Vec<u64>,
+    blocks: MinTable,
+}
+
+impl<T: Ord> BlockRmq<T> {
+    /// builds the index over `values` and takes ownership of them
+    pub fn new(values: Vec<T>) -> Self {
+        let mut stack_masks = Vec::with_capacity(values.len());
+        let mut block_minima = Vec::with_capacity(values.len().div_ceil(BLOCK_SIZE));
+        for (block, block_values) in values.chunks(BLOCK_SIZE).enumerate() {
+            let mut stack = 0u64;
+            for (i, value) in block_values.iter().enumerate() {
+                // equal elements stay on the stack so the leftmost one is found
+                while stack != 0 && block_values[stack.ilog2() as usize] > *value {
+                    stack &= !(1 << stack.ilog2());
+                }
+                stack |= 1 << i;
+                stack_masks.push(stack);
+            }
+            // the bottom of the stack is the minimum of the whole block
+            block_minima.push(block * BLOCK_SIZE + stack.trailing_zeros() as usize);
+        }
+        let blocks = MinTable::new(block_minima, |a, b| values[a] < values[b]);
+        Self {
+            values,
+            stack_masks,
+            blocks,
+        }
+    }
+
+    /// returns the position of the minimum element in `range`
+    ///
+    /// returns `None` if the range is empty or not contained in the index
+    pub fn range_minimum(&self, range: impl core::ops::RangeBounds<usize>) -> Option<usize> {
+        self.try_range_minimum(range).ok()
+    }
+
+    /// returns the position of the minimum element in `range`
+    /// or the reason why the range cannot be queried
+    pub fn try_range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        let range = resolve_range(range, self.len())?;
+        let (start, end) = (*range.start(), *range.end());
+        let (start_block, end_block) = (start / BLOCK_SIZE, end / BLOCK_SIZE);
+        if start_block == end_block {
+            return Ok(self.in_block_minimum(start, end));
+        }
+
+        let less = |a: usize, b: usize| self.values[a] < self.values[b];
+        let mut min = self.in_block_minimum(start, (start_block + 1) * BLOCK_SIZE - 1);
+        if start_block + 1 < end_block {
+            let blocks_min = self.blocks.query(start_block + 1, end_block - 1, less);
+            if less(blocks_min, min) {
+                min = blocks_min;
+            }
+        }
+        let end_min = self.in_block_minimum(end_block * BLOCK_SIZE, end);
+        if less(end_min, min) {
+            min = end_min;
+        }
+        Ok(min)
+    }
+
+    /// returns the minimum of `start..=end` within a single block
+    fn in_block_minimum(&self, start: usize, end: usize) -> usize {
+        // the first element on the stack at or after `start` is the minimum
+        let stack = self.stack_masks[end] & (!0 << (start % BLOCK_SIZE));
+        start - start % BLOCK_SIZE + stack.trailing_zeros() as usize
+    }
+}
+
+impl<T> BlockRmq<T> {
+    /// returns the indexed values
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// returns the number of indexed values
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// returns `true` if no values are indexed
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// returns the heap memory used by the index in bytes, without the values
+    pub fn heap_size_in_bytes(&self) -> usize {
+        self.stack_masks.capacity() * core::mem::size_of::<u64>() + self.blocks.heap_size_in_bytes()
+    }
+
+    /// consumes the index and returns the indexed values
+    pub fn into_values(self) -> Vec<T> {
+        self.values
+    }
+}
+
+impl<T: Ord> FromIterator<T> for BlockRmq<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+impl<T: Ord> RangeMinimum for BlockRmq<T> {
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn try_range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        BlockRmq::try_range_minimum(self, range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::BlockRmq;
+
+    proptest! {
+        #[test]
+        fn block_rmq_matches_scan(
+            elems in prop::collection::vec(0u8..16, 1..2000),
+            start in any::<prop::sample::Index>(),
+            end in any::<prop::sample::Index>()
+        ) {
+            let rmq = BlockRmq::new(elems.clone());
+            let (start, end) = (start.index(elems.len()), end.index(elems.len()));
+            let range = start.min(end)..=start.max(end);
+
+            let expected = range.clone().min_by_key(|&i| (elems[i], i));
+            prop_assert_eq!(rmq.range_minimum(range), expected);
+        }
+    }
+}
@@ -1,31 +1,261 @@
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
 use bitvec::prelude::*;
+
+use crate::layout::{self, Layout};
+
+mod excess_min;
+mod excess_search;
 mod excess_tables;
 mod rank_select;
 
-use self::excess_tables::{FWD_EXC, FWD_MIN, FWD_MIN_IDX};
+pub use self::excess_min::ExcessBackend;
+pub(crate) use self::rank_select::BLOCK_SIZE as RANK_BLOCK_SIZE;
 
 pub type BitVec64 = BitVec<u64, Lsb0>;
 
-const BP_BLOCK_SIZE: usize = 4;
-const BP_SUPERBLOCK_SIZE: usize = 32;
-
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct BpBitVec {
+#[cfg_attr(feature = "serde", serde(bound = "L: Layout"))]
+pub struct BpBitVec<L = layout::Balanced> {
     bv: BitVec64,
     select0_hints: Vec<u64>,
     block_rank_pairs: Vec<u64>,
     internal_nodes: u64,
     block_excess_min: Vec<i16>,
-    superblock_excess_min: Vec<isize>,
+    superblock_excess_min: Vec<i64>,
+    /// stored as the id of the layout, which has to match on deserialization
+    #[cfg_attr(feature = "serde", serde(with = "layout_id"))]
+    layout: PhantomData<L>,
+}
+
+#[cfg(feature = "serde")]
+mod layout_id {
+    use core::marker::PhantomData;
+
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::layout::Layout;
+    use crate::FormatError;
+
+    pub(super) fn serialize<L: Layout, S: Serializer>(
+        _: &PhantomData<L>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(L::ID)
+    }
+
+    pub(super) fn deserialize<'de, L: Layout, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PhantomData<L>, D::Error> {
+        if u32::deserialize(deserializer)? == L::ID {
+            Ok(PhantomData)
+        } else {
+            Err(D::Error::custom(FormatError::LayoutMismatch))
+        }
+    }
+}
+
+/// Borrowed view of the components of a [`BpBitVec`]
+///
+/// All queries are implemented on the view so they can run
+/// both on an owned bit vector and on serialized bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct BpRef<'a, L = layout::Balanced> {
+    pub(crate) bv: &'a [u64],
+    pub(crate) len: usize,
+    pub(crate) select0_hints: &'a [u64],
+    pub(crate) block_rank_pairs: &'a [u64],
+    pub(crate) internal_nodes: u64,
+    pub(crate) block_excess_min: &'a [i16],
+    pub(crate) superblock_excess_min: &'a [i64],
+    pub(crate) layout: PhantomData<L>,
+}
+
+/// Types that can be queried as balanced parentheses
+pub trait AsBpRef {
+    type Layout: Layout;
+
+    fn as_bp_ref(&self) -> BpRef<'_, Self::Layout>;
+}
+
+impl<L: Layout> AsBpRef for BpBitVec<L> {
+    type Layout = L;
+
+    fn as_bp_ref(&self) -> BpRef<'_, L> {
+        BpRef {
+            bv: self.bv.as_raw_slice(),
+            len: self.bv.len(),
+            select0_hints: &self.select0_hints,
+            block_rank_pairs: &self.block_rank_pairs,
+            internal_nodes: self.internal_nodes,
+            block_excess_min: &self.block_excess_min,
+            superblock_excess_min: &self.superblock_excess_min,
+            layout: PhantomData,
+        }
+    }
+}
+
+impl<L: Layout> BpRef<'_, L> {
+    /// checks that the sizes of all components match the length of the bit vector,
+    /// so that queries stay within bounds
+    pub fn has_valid_layout(&self) -> bool {
+        let n_words = self.bv.len();
+        let n_rank_blocks = n_words.div_ceil(rank_select::BLOCK_SIZE);
+        let n_blocks = n_words.div_ceil(L::BLOCK_WORDS);
+        let n_superblocks = n_blocks.div_ceil(L::SUPERBLOCK_BLOCKS);
+        let internal_nodes = n_superblocks.next_power_of_two();
+        self.len >= 2
+            && self.len % 2 == 0
+            && n_words == self.len.div_ceil(64)
+            && self.block_rank_pairs.len() == 2 * (n_rank_blocks + 1)
+            && self.select0_hints.last() == Some(&(n_rank_blocks as u64))
+            && self
+                .select0_hints
+                .iter()
+                .all(|&h| h <= n_rank_blocks as u64)
+            && self.block_excess_min.len() == n_blocks
+            && self.internal_nodes == internal_nodes as u64
+            && self.superblock_excess_min.len() == internal_nodes + n_superblocks
+    }
+
+    /// checks that the bits form an enclosed balanced parentheses sequence and
+    /// that the auxiliary structures match the ones built from these bits
+    ///
+    /// runs in time linear in the number of words without allocating,
+    /// the sizes have to be checked with [`has_valid_layout`](Self::has_valid_layout) first
+    pub fn has_valid_contents(&self) -> bool {
+        self.is_enclosed()
+            && self.has_valid_rank_pairs()
+            && self.has_valid_select0_hints()
+            && self.has_valid_min_tree()
+    }
+
+    /// checks that the first parenthesis, the super-root, encloses all others
+    fn is_enclosed(&self) -> bool {
+        let bit = |pos: usize| self.bv[pos / 64] >> (pos % 64) & 1 == 1;
+        if !bit(0) || bit(self.len - 1) {
+            return false;
+        }
+        // the parentheses in between have to be balanced, which they are
+        // if the excess in front of the last one is 1 and never drops below
+        let inner_end = self.len - 1;
+        let mut excess = 1isize;
+        let backend = ExcessBackend::current();
+        for (i, &word) in self.bv.iter().enumerate() {
+            let start = usize::from(i == 0);
+            let n_bits = inner_end
+                .saturating_sub(i * 64)
+                .min(64)
+                .saturating_sub(start);
+            // pad with ones, they cannot lower the minimum
+            let padded_word = if n_bits == 64 {
+                word
+            } else {
+                word >> start | !0 << n_bits
+            };
+            let word_min = excess_min::word_excess_min(backend, padded_word);
+            if excess + word_min.min < 1 {
+                return false;
+            }
+            excess += word_min.total - (64 - n_bits) as isize;
+        }
+        excess == 1
+    }
+
+    fn has_valid_min_tree(&self) -> bool {
+        let n_blocks = self.block_excess_min.len();
+        let backend = ExcessBackend::current();
+        let blocks_match = (0..n_blocks).all(|block| {
+            self.block_excess_min[block]
+                == block_min_excess::<L>(backend, self.bv, self.len, self.block_rank_pairs, block)
+        });
+        let internal_nodes = self.internal_nodes as usize;
+        let tree = self.superblock_excess_min;
+        let leaves_match = (0..n_blocks.div_ceil(L::SUPERBLOCK_BLOCKS)).all(|superblock| {
+            tree[internal_nodes + superblock]
+                == superblock_min_excess::<L>(
+                    self.len,
+                    self.block_rank_pairs,
+                    self.block_excess_min,
+                    superblock,
+                )
+        });
+        // the root at index 1 is also a child of the node at index 0
+        let nodes_match = (0..internal_nodes).all(|node| {
+            let min = [2 * node, 2 * node + 1]
+                .into_iter()
+                .filter(|&child| child != node && child < tree.len())
+                .map(|child| tree[child])
+                .fold(self.len as i64, i64::min);
+            tree[node] == min
+        });
+        blocks_match && leaves_match && nodes_match
+    }
+
+    /// copies the borrowed components into an owned bit vector
+    pub fn to_bp_bitvec(self) -> BpBitVec<L> {
+        let mut bv = BitVec64::from_vec(self.bv.to_vec());
+        bv.truncate(self.len);
+        BpBitVec {
+            bv,
+            select0_hints: self.select0_hints.to_vec(),
+            block_rank_pairs: self.block_rank_pairs.to_vec(),
+            internal_nodes: self.internal_nodes,
+            block_excess_min: self.block_excess_min.to_vec(),
+            superblock_excess_min: self.superblock_excess_min.to_vec(),
+            layout: PhantomData,
+        }
+    }
 }
 
-impl BpBitVec {
-    pub fn from_bitvec(bv: BitVec64) -> Self {
-        let block_rank_pairs = rank_select::build_rank_pairs(&bv);
-        let select0_hints = rank_select::build_select0_hints(&block_rank_pairs);
+impl<L: Layout> AsBpRef for BpRef<'_, L> {
+    type Layout = L;
+
+    fn as_bp_ref(&self) -> BpRef<'_, L> {
+        *self
+    }
+}
+
+impl<L: Layout> BpBitVec<L> {
+    pub fn from_bitvec(mut bv: BitVec64) -> Self {
+        // the index is immutable, so drop the spare capacity left by the builders
+        bv.shrink_to_fit();
+        let mut block_rank_pairs = rank_select::build_rank_pairs(&bv);
+        block_rank_pairs.shrink_to_fit();
+        let mut select0_hints = rank_select::build_select0_hints::<L>(&block_rank_pairs);
+        select0_hints.shrink_to_fit();
         let (internal_nodes, block_excess_min, superblock_excess_min) =
-            build_min_tree(&bv, &block_rank_pairs);
+            build_min_tree::<L>(&bv, &block_rank_pairs);
+        Self {
+            bv,
+            select0_hints,
+            block_rank_pairs,
+            internal_nodes,
+            block_excess_min,
+            superblock_excess_min,
+            layout: PhantomData,
+        }
+    }
+
+    /// builds the rank, select and excess structures using the rayon thread pool
+    #[cfg(feature = "rayon")]
+    pub fn par_from_bitvec(mut bv: BitVec64) -> Self {
+        bv.shrink_to_fit();
+        let mut block_rank_pairs = rank_select::par_build_rank_pairs(&bv);
+        block_rank_pairs.shrink_to_fit();
+        let (select0_hints, (internal_nodes, block_excess_min, superblock_excess_min)) =
+            rayon::join(
+                || {
+                    let mut select0_hints =
+                        rank_select::par_build_select0_hints::<L>(&block_rank_pairs);
+                    select0_hints.shrink_to_fit();
+                    select0_hints
+                },
+                || par_build_min_tree::<L>(&bv, &block_rank_pairs),
+            );
         Self {
             bv,
             select0_hints,
@@ -33,14 +263,45 @@ impl BpBitVec {
             internal_nodes,
             block_excess_min,
             superblock_excess_min,
+            layout: PhantomData,
         }
     }
 
+    /// rebuilds the auxiliary structures of the same parentheses in layout `M`
+    pub fn into_layout<M: Layout>(self) -> BpBitVec<M> {
+        BpBitVec::from_bitvec(self.bv)
+    }
+
+    pub fn space_breakdown(&self) -> SpaceBreakdown {
+        fn vec_bytes<T>(v: &Vec<T>) -> usize {
+            v.capacity() * core::mem::size_of::<T>()
+        }
+        SpaceBreakdown {
+            bv: self.bv.capacity().div_ceil(64) * core::mem::size_of::<u64>(),
+            select0_hints: vec_bytes(&self.select0_hints),
+            block_rank_pairs: vec_bytes(&self.block_rank_pairs),
+            block_excess_min: vec_bytes(&self.block_excess_min),
+            superblock_excess_min: vec_bytes(&self.superblock_excess_min),
+            num_elements: self.bv.len() / 2 - 1,
+        }
+    }
+}
+
+impl<L: Layout> BpRef<'_, L> {
     pub fn excess(&self, offset: usize) -> isize {
         2 * self.rank1(offset as u64) as isize - offset as isize
     }
 
-    pub fn excess_rmq(&self, range: std::ops::RangeInclusive<usize>) -> (usize, isize) {
+    pub fn excess_rmq(&self, range: core::ops::RangeInclusive<usize>) -> (usize, isize) {
+        self.excess_rmq_with(ExcessBackend::current(), range)
+    }
+
+    /// searches the minimum excess with `backend`, which the running CPU has to support
+    pub(crate) fn excess_rmq_with(
+        &self,
+        backend: ExcessBackend,
+        range: core::ops::RangeInclusive<usize>,
+    ) -> (usize, isize) {
         let mut cur_excess = self.excess(*range.start());
         let mut min_excess = cur_excess;
         let mut min_excess_idx = *range.start();
@@ -64,6 +325,7 @@ impl BpBitVec {
         };
 
         excess_rmq_in_word(
+            backend,
             padded_word_start,
             *range.start() as u64,
             &mut cur_excess,
@@ -75,14 +337,15 @@ impl BpBitVec {
             return (min_excess_idx, min_excess);
         }
 
-        let block_start = word_start_idx / BP_BLOCK_SIZE;
-        let block_b = word_b_idx / BP_BLOCK_SIZE;
+        let block_start = word_start_idx / L::BLOCK_WORDS;
+        let block_b = word_b_idx / L::BLOCK_WORDS;
 
         cur_excess -= 64 - subword_len_start as isize; // remove padding
 
         if block_start == block_b {
             // same block
             self.excess_rmq_in_block(
+                backend,
                 word_start_idx + 1,
                 word_b_idx,
                 &mut cur_excess,
@@ -92,8 +355,9 @@ impl BpBitVec {
         } else {
             // search in partial block of word_a
             self.excess_rmq_in_block(
+                backend,
                 word_start_idx + 1,
-                (block_start + 1) * BP_BLOCK_SIZE,
+                (block_start + 1) * L::BLOCK_WORDS,
                 &mut cur_excess,
                 &mut min_excess,
                 &mut min_excess_idx,
@@ -103,8 +367,8 @@ impl BpBitVec {
             let mut block_min_excess = min_excess;
             let mut block_min_idx = usize::MAX;
 
-            let superblock_start = (block_start + 1) / BP_SUPERBLOCK_SIZE;
-            let superblock_b = block_b / BP_SUPERBLOCK_SIZE;
+            let superblock_start = (block_start + 1) / L::SUPERBLOCK_BLOCKS;
+            let superblock_b = block_b / L::SUPERBLOCK_BLOCKS;
 
             if superblock_start == superblock_b {
                 // same superblock
@@ -118,7 +382,7 @@ impl BpBitVec {
                 // partial superblock of a
                 self.excess_rmq_in_superblock(
                     block_start as u64 + 1,
-                    (superblock_start as u64 + 1) * BP_SUPERBLOCK_SIZE as u64,
+                    (superblock_start as u64 + 1) * L::SUPERBLOCK_BLOCKS as u64,
                     &mut block_min_excess,
                     &mut block_min_idx,
                 );
@@ -135,8 +399,8 @@ impl BpBitVec {
 
                 if superblock_min_excess < min_excess {
                     self.excess_rmq_in_superblock(
-                        superblock_min_idx as u64 * BP_SUPERBLOCK_SIZE as u64,
-                        (superblock_min_idx as u64 + 1) * BP_SUPERBLOCK_SIZE as u64,
+                        superblock_min_idx as u64 * L::SUPERBLOCK_BLOCKS as u64,
+                        (superblock_min_idx as u64 + 1) * L::SUPERBLOCK_BLOCKS as u64,
                         &mut block_min_excess,
                         &mut block_min_idx,
                     );
@@ -144,7 +408,7 @@ impl BpBitVec {
 
                 // partial superblock of b
                 self.excess_rmq_in_superblock(
-                    superblock_b as u64 * BP_SUPERBLOCK_SIZE as u64,
+                    superblock_b as u64 * L::SUPERBLOCK_BLOCKS as u64,
                     block_b as u64,
                     &mut block_min_excess,
                     &mut block_min_idx,
@@ -154,8 +418,9 @@ impl BpBitVec {
             if block_min_excess < min_excess {
                 cur_excess = self.get_block_excess(block_min_idx as u64);
                 self.excess_rmq_in_block(
-                    block_min_idx * BP_BLOCK_SIZE,
-                    (block_min_idx + 1) * BP_BLOCK_SIZE,
+                    backend,
+                    block_min_idx * L::BLOCK_WORDS,
+                    (block_min_idx + 1) * L::BLOCK_WORDS,
                     &mut cur_excess,
                     &mut min_excess,
                     &mut min_excess_idx,
@@ -165,7 +430,8 @@ impl BpBitVec {
             // search in partial block of word_b
             cur_excess = self.get_block_excess(block_b as u64);
             self.excess_rmq_in_block(
-                block_b * BP_BLOCK_SIZE,
+                backend,
+                block_b * L::BLOCK_WORDS,
                 word_b_idx,
                 &mut cur_excess,
                 &mut min_excess,
@@ -183,6 +449,7 @@ impl BpBitVec {
         };
 
         excess_rmq_in_word(
+            backend,
             padded_word_b,
             word_b_idx as u64 * 64,
             &mut cur_excess,
@@ -194,26 +461,83 @@ impl BpBitVec {
     }
 
     pub fn len(&self) -> usize {
-        self.bv.len()
+        self.len
+    }
+
+    pub fn bit(&self, pos: usize) -> bool {
+        (self.bit_word(pos as u64 / 64) >> (pos % 64)) & 1 == 1
+    }
+}
+
+/// Heap memory used by the components of the succinct index, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpaceBreakdown {
+    /// the balanced parentheses sequence
+    pub bv: usize,
+    /// the sampled positions used to speed up `select0`
+    pub select0_hints: usize,
+    /// the block and sub-block ranks
+    pub block_rank_pairs: usize,
+    /// the excess minimum of every block
+    pub block_excess_min: usize,
+    /// the min-tree over the superblock excess minima
+    pub superblock_excess_min: usize,
+    /// the number of indexed elements
+    pub num_elements: usize,
+}
+
+impl SpaceBreakdown {
+    /// returns the sum of all components in bytes
+    pub fn total_bytes(&self) -> usize {
+        self.bv
+            + self.select0_hints
+            + self.block_rank_pairs
+            + self.block_excess_min
+            + self.superblock_excess_min
+    }
+
+    /// returns the number of bits used per indexed element
+    pub fn bits_per_element(&self) -> f64 {
+        if self.num_elements == 0 {
+            return 0.0;
+        }
+        (self.total_bytes() * 8) as f64 / self.num_elements as f64
     }
 }
 
-impl BpBitVec {
+impl<L: Layout> BpRef<'_, L> {
     fn bit_word(&self, offset: u64) -> u64 {
-        self.bv.as_raw_slice()[offset as usize]
+        self.bv[offset as usize]
+    }
+
+    fn superblock_min(&self, node: u64) -> isize {
+        self.superblock_excess_min[node as usize] as isize
     }
 
     fn excess_rmq_in_block(
         &self,
+        backend: ExcessBackend,
         start: usize,
         end: usize,
         excess: &mut isize,
         min_excess: &mut isize,
         min_excess_idx: &mut usize,
     ) {
-        for idx in start..end {
+        // runs of four words are searched at once, independent of the block size
+        let mut chunks = self.bv[start..end].chunks_exact(4);
+        for (chunk_idx, chunk) in (&mut chunks).enumerate() {
+            let chunk_min = excess_min::block_excess_min(backend, chunk.try_into().unwrap());
+            if *excess + chunk_min.min < *min_excess {
+                *min_excess = *excess + chunk_min.min;
+                *min_excess_idx = (start + 4 * chunk_idx) * 64 + chunk_min.idx;
+            }
+            *excess += chunk_min.total;
+        }
+        let rest_start = end - chunks.remainder().len();
+        for (idx, &word) in (rest_start..end).zip(chunks.remainder()) {
             excess_rmq_in_word(
-                self.bit_word(idx as u64),
+                backend,
+                word,
                 idx as u64 * 64,
                 excess,
                 min_excess,
@@ -230,9 +554,9 @@ impl BpBitVec {
         block_min_idx: &mut usize,
     ) {
         if block_start != block_end {
-            let superblock = block_start / BP_SUPERBLOCK_SIZE as u64;
+            let superblock = block_start / L::SUPERBLOCK_BLOCKS as u64;
 
-            let superblock_excess = self.get_block_excess(superblock * BP_SUPERBLOCK_SIZE as u64);
+            let superblock_excess = self.get_block_excess(superblock * L::SUPERBLOCK_BLOCKS as u64);
 
             for block in block_start..block_end {
                 if superblock_excess + (self.block_excess_min[block as usize] as isize)
@@ -259,7 +583,7 @@ impl BpBitVec {
         let mut cur_node = self.internal_nodes + superblock_start;
         let mut rightmost_span = superblock_start;
 
-        let mut node_min_excess = self.superblock_excess_min[cur_node as usize];
+        let mut node_min_excess = self.superblock_min(cur_node);
         let mut node_min_idx = cur_node;
 
         if superblock_end - superblock_start == 1 {
@@ -276,9 +600,9 @@ impl BpBitVec {
                 rightmost_span += 1u64 << h;
 
                 if rightmost_span < superblock_end
-                    && self.superblock_excess_min[right_sibling as usize] < node_min_excess
+                    && self.superblock_min(right_sibling) < node_min_excess
                 {
-                    node_min_excess = self.superblock_excess_min[right_sibling as usize];
+                    node_min_excess = self.superblock_min(right_sibling);
                     node_min_idx = right_sibling;
                 }
 
@@ -304,8 +628,8 @@ impl BpBitVec {
             } else {
                 // go to right child and add left subtree to candidate
                 // subblocks
-                if self.superblock_excess_min[left_child as usize] < node_min_excess {
-                    node_min_excess = self.superblock_excess_min[left_child as usize];
+                if self.superblock_min(left_child) < node_min_excess {
+                    node_min_excess = self.superblock_min(left_child);
                     node_min_idx = left_child;
                 }
                 cur_node = left_child + 1;
@@ -313,10 +637,8 @@ impl BpBitVec {
         }
 
         // check last left-turn
-        if rightmost_span < superblock_end
-            && self.superblock_excess_min[cur_node as usize] < node_min_excess
-        {
-            node_min_excess = self.superblock_excess_min[cur_node as usize];
+        if rightmost_span < superblock_end && self.superblock_min(cur_node) < node_min_excess {
+            node_min_excess = self.superblock_min(cur_node);
             node_min_idx = cur_node;
         }
 
@@ -325,9 +647,7 @@ impl BpBitVec {
             while cur_node < self.internal_nodes {
                 cur_node *= 2;
                 // remember that past-the-end nodes are filled with size()
-                if self.superblock_excess_min[cur_node as usize + 1]
-                    < self.superblock_excess_min[cur_node as usize]
-                {
+                if self.superblock_min(cur_node + 1) < self.superblock_min(cur_node) {
                     cur_node += 1;
                 }
             }
@@ -338,105 +658,167 @@ impl BpBitVec {
     }
 
     fn get_block_excess(&self, block: u64) -> isize {
-        let sub_block_idx = block * BP_BLOCK_SIZE as u64;
+        let sub_block_idx = block * L::BLOCK_WORDS as u64;
         let block_pos = sub_block_idx * 64;
         (2 * self.sub_block_rank(sub_block_idx) - block_pos) as isize
     }
 }
 
-fn build_min_tree(bitvec: &BitVec64, block_rank_pairs: &[u64]) -> (u64, Vec<i16>, Vec<isize>) {
-    const fn get_block_excess(block: u64, block_rank_pairs: &[u64]) -> isize {
-        const fn block_rank1(block: u64, block_rank_pairs: &[u64]) -> u64 {
-            block_rank_pairs[block as usize * 2] as u64
-        }
-
-        const fn sub_block_ranks(sub_block: u64, block_rank_pairs: &[u64]) -> u64 {
-            block_rank_pairs[sub_block as usize * 2 + 1] as u64
-        }
+fn build_min_tree<L: Layout>(
+    bitvec: &BitVec64,
+    block_rank_pairs: &[u64],
+) -> (u64, Vec<i16>, Vec<i64>) {
+    let n_blocks = bitvec.as_raw_slice().len().div_ceil(L::BLOCK_WORDS);
+    let backend = ExcessBackend::current();
+    let block_excess_min: Vec<i16> = (0..n_blocks)
+        .map(|block| {
+            block_min_excess::<L>(
+                backend,
+                bitvec.as_raw_slice(),
+                bitvec.len(),
+                block_rank_pairs,
+                block,
+            )
+        })
+        .collect();
+    let leaves: Vec<i64> = (0..n_blocks.div_ceil(L::SUPERBLOCK_BLOCKS))
+        .map(|superblock| {
+            superblock_min_excess::<L>(
+                bitvec.len(),
+                block_rank_pairs,
+                &block_excess_min,
+                superblock,
+            )
+        })
+        .collect();
+    let (internal_nodes, superblock_excess_min) = min_tree_from_leaves(bitvec, leaves);
+    (internal_nodes, block_excess_min, superblock_excess_min)
+}
 
-        const fn sub_block_rank(sub_block: u64, block_rank_pairs: &[u64]) -> u64 {
-            let mut r: u64 = 0;
-            let block = sub_block / rank_select::BLOCK_SIZE as u64;
-            r += block_rank1(block, block_rank_pairs);
-            let left = sub_block % rank_select::BLOCK_SIZE as u64;
-            r += sub_block_ranks(block, block_rank_pairs) >> ((7 - left) * 9) & 0x1FF;
-            r
-        }
-        let sub_block_idx = block * BP_BLOCK_SIZE as u64;
-        let block_pos = sub_block_idx * 64;
+#[cfg(feature = "rayon")]
+fn par_build_min_tree<L: Layout>(
+    bitvec: &BitVec64,
+    block_rank_pairs: &[u64],
+) -> (u64, Vec<i16>, Vec<i64>) {
+    use rayon::prelude::*;
+
+    let n_blocks = bitvec.as_raw_slice().len().div_ceil(L::BLOCK_WORDS);
+    let backend = ExcessBackend::current();
+    let block_excess_min: Vec<i16> = (0..n_blocks)
+        .into_par_iter()
+        .map(|block| {
+            block_min_excess::<L>(
+                backend,
+                bitvec.as_raw_slice(),
+                bitvec.len(),
+                block_rank_pairs,
+                block,
+            )
+        })
+        .collect();
+    let leaves: Vec<i64> = (0..n_blocks.div_ceil(L::SUPERBLOCK_BLOCKS))
+        .into_par_iter()
+        .map(|superblock| {
+            superblock_min_excess::<L>(
+                bitvec.len(),
+                block_rank_pairs,
+                &block_excess_min,
+                superblock,
+            )
+        })
+        .collect();
+    let (internal_nodes, superblock_excess_min) = min_tree_from_leaves(bitvec, leaves);
+    (internal_nodes, block_excess_min, superblock_excess_min)
+}
 
-        2 * sub_block_rank(sub_block_idx, block_rank_pairs) as isize - block_pos as isize
+const fn get_block_excess<L: Layout>(block: u64, block_rank_pairs: &[u64]) -> isize {
+    const fn block_rank1(block: u64, block_rank_pairs: &[u64]) -> u64 {
+        block_rank_pairs[block as usize * 2]
     }
 
-    let mut block_excess_min = Vec::new();
-    let mut cur_block_min = 0;
-    let mut cur_superblock_excess = 0;
-    let words = bitvec.as_raw_slice();
-    for (sub_block, &word) in words.iter().enumerate() {
-        if sub_block % BP_BLOCK_SIZE == 0 {
-            if sub_block % (BP_BLOCK_SIZE * BP_SUPERBLOCK_SIZE) == 0 {
-                cur_superblock_excess = 0;
-            }
-
-            if sub_block != 0 {
-                block_excess_min.push(cur_block_min as i16);
-                cur_block_min = cur_superblock_excess;
-            }
-        }
-        let mut mask = 1;
-        // for last block stop at bit boundary
-        let n_bits = match sub_block == words.len() - 1 {
-            true if bitvec.len() % 64 != 0 => bitvec.len() % 64,
-            _ => 64,
-        };
-        for _i in 0..n_bits {
-            cur_superblock_excess += if (word & mask) != 0 { 1 } else { -1 };
-            cur_block_min = cur_block_min.min(cur_superblock_excess);
-            mask <<= 1;
-        }
+    const fn sub_block_ranks(sub_block: u64, block_rank_pairs: &[u64]) -> u64 {
+        block_rank_pairs[sub_block as usize * 2 + 1]
     }
 
-    // Flush last block mins
-    block_excess_min.push(cur_block_min as i16);
-
-    let n_blocks = words.len() / BP_BLOCK_SIZE + usize::from(words.len() % BP_BLOCK_SIZE != 0);
-    let n_superblocks = (n_blocks + BP_SUPERBLOCK_SIZE - 1) / BP_SUPERBLOCK_SIZE;
-
-    assert_eq!(n_blocks, block_excess_min.len());
-
-    let mut n_complete_leaves = 1;
-    while n_complete_leaves < n_superblocks {
-        n_complete_leaves <<= 1;
+    const fn sub_block_rank(sub_block: u64, block_rank_pairs: &[u64]) -> u64 {
+        let mut r: u64 = 0;
+        let block = sub_block / rank_select::BLOCK_SIZE as u64;
+        r += block_rank1(block, block_rank_pairs);
+        let left = sub_block % rank_select::BLOCK_SIZE as u64;
+        r += sub_block_ranks(block, block_rank_pairs) >> ((7 - left) * 9) & 0x1FF;
+        r
     }
-    // n_complete_leaves is the smallest power of 2 >= n_superblocks
-    let internal_nodes = n_complete_leaves;
-    let treesize = internal_nodes + n_superblocks;
-
-    let mut superblock_excess_min = vec![0isize; treesize];
+    let sub_block_idx = block * L::BLOCK_WORDS as u64;
+    let block_pos = sub_block_idx * 64;
 
-    // Fill in the leaves of the tree
-    for superblock in 0..n_superblocks {
-        let mut cur_super_min = bitvec.len() as isize;
-        let superblock_excess = get_block_excess(
-            superblock as u64 * BP_SUPERBLOCK_SIZE as u64,
-            block_rank_pairs,
-        );
+    2 * sub_block_rank(sub_block_idx, block_rank_pairs) as isize - block_pos as isize
+}
 
-        let start = superblock * BP_SUPERBLOCK_SIZE;
-        let stop = n_blocks.min((superblock + 1) * BP_SUPERBLOCK_SIZE);
+/// returns the minimum excess in `block` relative to the start of its superblock,
+/// including the excess in front of the first bit of the block
+fn block_min_excess<L: Layout>(
+    backend: ExcessBackend,
+    words: &[u64],
+    len: usize,
+    block_rank_pairs: &[u64],
+    block: usize,
+) -> i16 {
+    let superblock_start = (block / L::SUPERBLOCK_BLOCKS * L::SUPERBLOCK_BLOCKS) as u64;
+    let mut cur_excess = get_block_excess::<L>(block as u64, block_rank_pairs)
+        - get_block_excess::<L>(superblock_start, block_rank_pairs);
+    let mut cur_block_min = cur_excess;
+    let block_end = words.len().min((block + 1) * L::BLOCK_WORDS);
+    for (sub_block, &word) in words
+        .iter()
+        .enumerate()
+        .take(block_end)
+        .skip(block * L::BLOCK_WORDS)
+    {
+        // for last block stop at bit boundary, the padding ones cannot lower the minimum
+        let n_bits = 64.min(len - sub_block * 64);
+        let padded_word = if n_bits == 64 {
+            word
+        } else {
+            word | (!0 << n_bits)
+        };
+        let word_min = excess_min::word_excess_min(backend, padded_word);
+        cur_block_min = cur_block_min.min(cur_excess + word_min.min);
+        cur_excess += word_min.total;
+    }
+    cur_block_min as i16
+}
 
-        for &cur_block_excess in block_excess_min.iter().take(stop).skip(start) {
-            cur_super_min = cur_super_min.min(superblock_excess + cur_block_excess as isize);
-        }
+fn superblock_min_excess<L: Layout>(
+    len: usize,
+    block_rank_pairs: &[u64],
+    block_excess_min: &[i16],
+    superblock: usize,
+) -> i64 {
+    let superblock_excess = get_block_excess::<L>(
+        superblock as u64 * L::SUPERBLOCK_BLOCKS as u64,
+        block_rank_pairs,
+    );
+    block_excess_min
+        .iter()
+        .skip(superblock * L::SUPERBLOCK_BLOCKS)
+        .take(L::SUPERBLOCK_BLOCKS)
+        .map(|&cur_block_excess| superblock_excess as i64 + cur_block_excess as i64)
+        .fold(len as i64, i64::min)
+}
 
-        superblock_excess_min[internal_nodes + superblock] = cur_super_min;
-    }
+/// lays out the superblock minima as the leaves of a complete binary tree
+/// and fills in the minima of the internal nodes
+fn min_tree_from_leaves(bitvec: &BitVec64, leaves: Vec<i64>) -> (u64, Vec<i64>) {
+    let n_superblocks = leaves.len();
+    // the smallest power of 2 >= n_superblocks
+    let internal_nodes = n_superblocks.next_power_of_two();
+    let treesize = internal_nodes + n_superblocks;
 
     // fill in the internal nodes with past-the-boundary values
     // (they will also serve as sentinels in debug)
-    for cur_superblock_excess_min in superblock_excess_min.iter_mut().take(internal_nodes) {
-        *cur_superblock_excess_min = bitvec.len() as isize;
-    }
+    let mut superblock_excess_min = Vec::with_capacity(treesize);
+    superblock_excess_min.resize(internal_nodes, bitvec.len() as i64);
+    superblock_excess_min.extend(leaves);
 
     // Fill bottom-up the other layers: each node updates the parent
     for node in (1..treesize).rev() {
@@ -445,41 +827,103 @@ fn build_min_tree(bitvec: &BitVec64, block_rank_pairs: &[u64]) -> (u64, Vec<i16>
             superblock_excess_min[parent].min(superblock_excess_min[node]);
     }
 
-    (
-        internal_nodes as u64,
-        block_excess_min,
-        superblock_excess_min,
-    )
+    (internal_nodes as u64, superblock_excess_min)
 }
 
 fn excess_rmq_in_word(
+    backend: ExcessBackend,
     word: u64,
     word_start: u64,
     excess: &mut isize,
     min_excess: &mut isize,
     min_excess_idx: &mut usize,
 ) {
-    let mut min_byte_idx = 0;
-    let mut min_byte_excess = *min_excess;
-    for i in 0..8 {
-        let shift = i * 8;
-        let byte = (word >> shift) & 0xFF;
+    let word_min = excess_min::word_excess_min(backend, word);
+    if *excess + word_min.min < *min_excess {
+        *min_excess = *excess + word_min.min;
+        *min_excess_idx = word_start as usize + word_min.idx;
+    }
+    *excess += word_min.total;
+}
 
-        let cur_min = *excess - FWD_MIN[byte as usize] as isize;
+/// turns random steps into a balanced parentheses sequence
+#[cfg(test)]
+pub(crate) fn balanced(steps: &[bool]) -> BitVec64 {
+    let mut bv = BitVec64::new();
+    let mut excess = 0;
+    for &open in steps {
+        let open = open || excess == 0;
+        excess += if open { 1 } else { -1 };
+        bv.push(open);
+    }
+    bv.extend(core::iter::repeat_n(false, excess as usize));
+    bv
+}
 
-        if cur_min < min_byte_excess {
-            min_byte_idx = i;
-            min_byte_excess = cur_min;
-        }
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::{
+        build_min_tree, get_block_excess, min_tree_from_leaves, rank_select, superblock_min_excess,
+        BitVec64,
+    };
+    use crate::layout::{self, Layout};
+
+    /// the excess minima of the blocks, computed one bit at a time
+    fn block_excess_min_bitwise<L: Layout>(
+        bitvec: &BitVec64,
+        block_rank_pairs: &[u64],
+    ) -> Vec<i16> {
+        let n_blocks = bitvec.as_raw_slice().len().div_ceil(L::BLOCK_WORDS);
+        (0..n_blocks)
+            .map(|block| {
+                let superblock_start = (block / L::SUPERBLOCK_BLOCKS * L::SUPERBLOCK_BLOCKS) as u64;
+                let mut excess = get_block_excess::<L>(block as u64, block_rank_pairs)
+                    - get_block_excess::<L>(superblock_start, block_rank_pairs);
+                let mut min = excess;
+                let start = block * L::BLOCK_WORDS * 64;
+                let end = bitvec.len().min(start + L::BLOCK_WORDS * 64);
+                for bit in &bitvec[start..end] {
+                    excess += if *bit { 1 } else { -1 };
+                    min = min.min(excess);
+                }
+                min as i16
+            })
+            .collect()
+    }
 
-        *excess += FWD_EXC[byte as usize] as isize;
+    fn assert_min_tree<L: Layout>(bitvec: &BitVec64) {
+        let block_rank_pairs = rank_select::build_rank_pairs(bitvec);
+        let block_excess_min = block_excess_min_bitwise::<L>(bitvec, &block_rank_pairs);
+        let leaves = (0..block_excess_min.len().div_ceil(L::SUPERBLOCK_BLOCKS))
+            .map(|superblock| {
+                superblock_min_excess::<L>(
+                    bitvec.len(),
+                    &block_rank_pairs,
+                    &block_excess_min,
+                    superblock,
+                )
+            })
+            .collect();
+        let (internal_nodes, superblock_excess_min) = min_tree_from_leaves(bitvec, leaves);
+
+        let actual = build_min_tree::<L>(bitvec, &block_rank_pairs);
+        assert_eq!(
+            actual,
+            (internal_nodes, block_excess_min, superblock_excess_min)
+        );
     }
 
-    if min_byte_excess < *min_excess {
-        *min_excess = min_byte_excess;
-        let shift = min_byte_idx * 8;
-        *min_excess_idx = word_start as usize
-            + shift as usize
-            + FWD_MIN_IDX[((word >> shift) & 0xFF) as usize] as usize;
+    proptest! {
+        #[test]
+        fn word_parallel_min_tree_matches_bitwise(
+            bits in prop::collection::vec(prop::bool::weighted(0.45), 1..40_000)
+        ) {
+            let bitvec: BitVec64 = bits.into_iter().collect();
+            assert_min_tree::<layout::Compact>(&bitvec);
+            assert_min_tree::<layout::Balanced>(&bitvec);
+            assert_min_tree::<layout::Fast>(&bitvec);
+        }
     }
 }
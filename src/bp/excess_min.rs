@@ -0,0 +1,494 @@
+use super::excess_tables::{FWD_EXC, FWD_MIN, FWD_MIN_IDX};
+use crate::util::{Backend, BackendCell};
+
+/// The minimum excess within a word or block of words, relative to its start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExcessMin {
+    /// the minimum excess, at most zero as the position in front of the first bit counts
+    pub min: isize,
+    /// the leftmost number of bits after which the minimum is reached
+    pub idx: usize,
+    /// the excess after all bits
+    pub total: isize,
+}
+
+/// Implementations of the search for the minimum excess within a word or block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExcessBackend {
+    /// walks the bytes through lookup tables one at a time
+    Lookup,
+    /// portable SWAR computation of the minima of all bytes of a word at once,
+    /// never detected as the lookup tables are faster on common CPUs
+    Swar,
+    /// SSE4.1 computation of the minima of all bytes of a word at once
+    Sse41,
+    /// AVX2 computation of the minima of all bytes of a block at once
+    Avx2,
+}
+
+static EXCESS_BACKEND: BackendCell<ExcessBackend> = BackendCell::new();
+
+impl ExcessBackend {
+    /// returns `Avx2`, else `Sse41` if the running CPU supports them and `Lookup` otherwise
+    ///
+    /// `cargo bench --bench excess` on an Intel Xeon (Sapphire Rapids, KVM guest) answers
+    /// 10 000 queries over a million values in 6.9 to 7.7 ms with `Lookup`, 5.6 to 6.6 ms
+    /// with `Sse41` and 5.7 to 6.1 ms with `Avx2`, depending on the input
+    ///
+    /// without the `std` feature the SIMD backends need their target features enabled
+    /// at compile time
+    pub fn detect() -> Self {
+        if Self::Avx2.is_supported() {
+            Self::Avx2
+        } else if Self::Sse41.is_supported() {
+            Self::Sse41
+        } else {
+            Self::Lookup
+        }
+    }
+
+    /// returns `true` if the backend can run here, `Lookup` and `Swar` always can
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::Lookup | Self::Swar => true,
+            Self::Sse41 => sse41_supported(),
+            Self::Avx2 => avx2_supported(),
+        }
+    }
+
+    /// returns the backend that queries and builds starting now search with
+    #[inline]
+    pub fn current() -> Self {
+        EXCESS_BACKEND.load()
+    }
+
+    /// searches with this backend in all queries and builds that start afterwards,
+    /// the ones already running keep the backend they started with
+    ///
+    /// # Panics
+    ///
+    /// if the running CPU does not support the backend
+    pub fn force(self) {
+        EXCESS_BACKEND.force(self);
+    }
+}
+
+impl Backend for ExcessBackend {
+    const ALL: &'static [Self] = &[Self::Lookup, Self::Swar, Self::Sse41, Self::Avx2];
+
+    fn detect() -> Self {
+        Self::detect()
+    }
+
+    fn is_supported(self) -> bool {
+        Self::is_supported(self)
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+fn sse41_supported() -> bool {
+    std::is_x86_feature_detected!("sse4.1")
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+fn avx2_supported() -> bool {
+    std::is_x86_feature_detected!("avx2")
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "std")))]
+fn sse41_supported() -> bool {
+    cfg!(target_feature = "sse4.1")
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "std")))]
+fn avx2_supported() -> bool {
+    cfg!(target_feature = "avx2")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn sse41_supported() -> bool {
+    false
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn avx2_supported() -> bool {
+    false
+}
+
+/// returns the minimum excess within `word`, searched with `backend`
+/// which the running CPU has to support
+#[inline]
+pub fn word_excess_min(backend: ExcessBackend, word: u64) -> ExcessMin {
+    match backend {
+        ExcessBackend::Lookup => word_excess_min_lookup(word),
+        ExcessBackend::Swar => word_excess_min_swar(word),
+        // SAFETY: callers only pass backends the CPU supports
+        #[cfg(target_arch = "x86_64")]
+        ExcessBackend::Sse41 | ExcessBackend::Avx2 => unsafe { word_excess_min_sse41(word) },
+        #[cfg(not(target_arch = "x86_64"))]
+        ExcessBackend::Sse41 | ExcessBackend::Avx2 => unreachable!(),
+    }
+}
+
+/// returns the minimum excess within the four words of `block`, searched with `backend`
+/// which the running CPU has to support
+#[inline]
+pub fn block_excess_min(backend: ExcessBackend, block: &[u64; 4]) -> ExcessMin {
+    match backend {
+        // SAFETY: callers only pass backends the CPU supports
+        #[cfg(target_arch = "x86_64")]
+        ExcessBackend::Avx2 => unsafe { block_excess_min_avx2(block) },
+        _ => {
+            let mut result = ExcessMin {
+                min: 0,
+                idx: 0,
+                total: 0,
+            };
+            for (i, &word) in block.iter().enumerate() {
+                let word_min = word_excess_min(backend, word);
+                if result.total + word_min.min < result.min {
+                    result.min = result.total + word_min.min;
+                    result.idx = i * 64 + word_min.idx;
+                }
+                result.total += word_min.total;
+            }
+            result
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn word_total(word: u64) -> isize {
+    2 * word.count_ones() as isize - 64
+}
+
+pub fn word_excess_min_lookup(word: u64) -> ExcessMin {
+    let mut excess = 0;
+    let mut min = 0;
+    let mut min_byte = 0;
+    for byte_idx in 0..8 {
+        let byte = ((word >> (byte_idx * 8)) & 0xFF) as usize;
+        if excess - (FWD_MIN[byte] as isize) < min {
+            min = excess - FWD_MIN[byte] as isize;
+            min_byte = byte_idx;
+        }
+        excess += FWD_EXC[byte] as isize;
+    }
+    ExcessMin {
+        min,
+        idx: byte_min_idx(word, min_byte, min),
+        total: excess,
+    }
+}
+
+/// returns the position of the minimum `min` in the byte `byte_idx`,
+/// or the start of the word if no byte goes below zero
+fn byte_min_idx(word: u64, byte_idx: usize, min: isize) -> usize {
+    if min == 0 {
+        return 0;
+    }
+    byte_idx * 8 + FWD_MIN_IDX[((word >> (byte_idx * 8)) & 0xFF) as usize] as usize
+}
+
+pub fn word_excess_min_swar(word: u64) -> ExcessMin {
+    const ONES: u64 = 0x0101_0101_0101_0101;
+    const HIGHS: u64 = 0x8080_8080_8080_8080;
+
+    // the running excess and its minimum of every byte, biased by 8 so
+    // each stays within its byte
+    let mut excess = 8 * ONES;
+    let mut min = excess;
+    for bit in 0..8 {
+        let ones = (word >> bit) & ONES;
+        excess = excess + 2 * ones - ONES;
+        // the high bit of a byte is set if its excess is at least its minimum
+        let at_least = ((excess | HIGHS) - min) & HIGHS;
+        let keep_min = (at_least >> 7) * 0xFF;
+        min = (min & keep_min) | (excess & !keep_min);
+    }
+
+    let mut before = 0;
+    let mut word_min = 0;
+    let mut min_byte = 0;
+    for byte_idx in 0..8 {
+        let shift = byte_idx * 8;
+        let byte_min = before + ((min >> shift) & 0xFF) as isize - 8;
+        if byte_min < word_min {
+            word_min = byte_min;
+            min_byte = byte_idx;
+        }
+        before += ((excess >> shift) & 0xFF) as isize - 8;
+    }
+    ExcessMin {
+        min: word_min,
+        idx: byte_min_idx(word, min_byte, word_min),
+        total: before,
+    }
+}
+
+/// the excess of every nibble
+#[cfg(target_arch = "x86_64")]
+const NIBBLE_EXC: [i8; 16] = nibble_table(false);
+
+/// the negated minimum excess of every nibble
+#[cfg(target_arch = "x86_64")]
+const NIBBLE_MIN: [i8; 16] = nibble_table(true);
+
+#[cfg(target_arch = "x86_64")]
+const fn nibble_table(min: bool) -> [i8; 16] {
+    let mut table = [0; 16];
+    let mut nibble = 0;
+    while nibble < 16 {
+        let mut excess = 0i8;
+        let mut min_excess = 0i8;
+        let mut bit = 0;
+        while bit < 4 {
+            excess += if (nibble >> bit) & 1 == 1 { 1 } else { -1 };
+            if excess < min_excess {
+                min_excess = excess;
+            }
+            bit += 1;
+        }
+        table[nibble] = if min { -min_excess } else { excess };
+        nibble += 1;
+    }
+    table
+}
+
+/// # Safety
+///
+/// the CPU has to support SSE4.1
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn word_excess_min_sse41(word: u64) -> ExcessMin {
+    use core::arch::x86_64::*;
+
+    let bytes = _mm_cvtsi64_si128(word as i64);
+    let low_nibbles = _mm_set1_epi8(0x0F);
+    let lo = _mm_and_si128(bytes, low_nibbles);
+    let hi = _mm_and_si128(_mm_srli_epi16(bytes, 4), low_nibbles);
+    let exc_table = _mm_loadu_si128(NIBBLE_EXC.as_ptr().cast());
+    let min_table = _mm_loadu_si128(NIBBLE_MIN.as_ptr().cast());
+    let lo_exc = _mm_shuffle_epi8(exc_table, lo);
+    let byte_exc = _mm_add_epi8(lo_exc, _mm_shuffle_epi8(exc_table, hi));
+    let byte_min = _mm_max_epi8(
+        _mm_shuffle_epi8(min_table, lo),
+        _mm_sub_epi8(_mm_shuffle_epi8(min_table, hi), lo_exc),
+    );
+
+    // the excess in front of every byte is the exclusive prefix sum of the byte excesses
+    let exc = _mm_cvtepi8_epi16(byte_exc);
+    let mut sum = _mm_add_epi16(exc, _mm_slli_si128(exc, 2));
+    sum = _mm_add_epi16(sum, _mm_slli_si128(sum, 4));
+    sum = _mm_add_epi16(sum, _mm_slli_si128(sum, 8));
+    let mins = _mm_sub_epi16(_mm_sub_epi16(sum, exc), _mm_cvtepi8_epi16(byte_min));
+
+    let (min, min_byte) = leftmost_min_epi16(mins);
+    let min = min.min(0);
+    ExcessMin {
+        min,
+        idx: byte_min_idx(word, min_byte, min),
+        total: word_total(word),
+    }
+}
+
+/// returns the minimum of the signed 16-bit lanes and its leftmost lane
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn leftmost_min_epi16(values: core::arch::x86_64::__m128i) -> (isize, usize) {
+    use core::arch::x86_64::*;
+
+    // flipping the sign bit orders the signed values as unsigned ones
+    let unsigned = _mm_xor_si128(values, _mm_set1_epi16(i16::MIN));
+    let min_pos = _mm_cvtsi128_si32(_mm_minpos_epu16(unsigned)) as u32;
+    let min = ((min_pos & 0xFFFF) as u16 ^ 0x8000) as i16;
+    (min as isize, (min_pos >> 16) as usize & 0x7)
+}
+
+/// # Safety
+///
+/// the CPU has to support AVX2
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn block_excess_min_avx2(block: &[u64; 4]) -> ExcessMin {
+    use core::arch::x86_64::*;
+
+    let bytes = _mm256_loadu_si256(block.as_ptr().cast());
+    let low_nibbles = _mm256_set1_epi8(0x0F);
+    let lo = _mm256_and_si256(bytes, low_nibbles);
+    let hi = _mm256_and_si256(_mm256_srli_epi16(bytes, 4), low_nibbles);
+    let exc_table = _mm256_broadcastsi128_si256(_mm_loadu_si128(NIBBLE_EXC.as_ptr().cast()));
+    let min_table = _mm256_broadcastsi128_si256(_mm_loadu_si128(NIBBLE_MIN.as_ptr().cast()));
+    let lo_exc = _mm256_shuffle_epi8(exc_table, lo);
+    let byte_exc = _mm256_add_epi8(lo_exc, _mm256_shuffle_epi8(exc_table, hi));
+    let byte_min = _mm256_max_epi8(
+        _mm256_shuffle_epi8(min_table, lo),
+        _mm256_sub_epi8(_mm256_shuffle_epi8(min_table, hi), lo_exc),
+    );
+
+    // the 32 bytes are widened into two vectors of 16 lanes, the prefix sums run
+    // within each 128-bit half and are carried over to the following halves
+    let (lo_mins, lo_total) = half_block_mins(
+        _mm256_castsi256_si128(byte_exc),
+        _mm256_castsi256_si128(byte_min),
+        0,
+    );
+    let (hi_mins, total) = half_block_mins(
+        _mm256_extracti128_si256(byte_exc, 1),
+        _mm256_extracti128_si256(byte_min, 1),
+        lo_total,
+    );
+
+    let mins = _mm256_min_epi16(lo_mins, hi_mins);
+    let (min, _) = leftmost_min_epi16(_mm_min_epi16(
+        _mm256_castsi256_si128(mins),
+        _mm256_extracti128_si256(mins, 1),
+    ));
+    let min = min.min(0);
+    // every lane equal to the minimum sets two bits of the mask
+    let target = _mm256_set1_epi16(min as i16);
+    let lo_mask = _mm256_movemask_epi8(_mm256_cmpeq_epi16(lo_mins, target)) as u32 as u64;
+    let hi_mask = _mm256_movemask_epi8(_mm256_cmpeq_epi16(hi_mins, target)) as u32 as u64;
+    let min_byte = ((hi_mask << 32) | lo_mask).trailing_zeros() as usize / 2;
+    let idx = if min == 0 {
+        0
+    } else {
+        let word = min_byte / 8;
+        word * 64 + byte_min_idx(block[word], min_byte % 8, min)
+    };
+    ExcessMin {
+        min,
+        idx,
+        total: total as isize,
+    }
+}
+
+/// returns the minimum excess of the 16 bytes, offset by the excess `carry`
+/// in front of them, and the excess after them
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn half_block_mins(
+    byte_exc: core::arch::x86_64::__m128i,
+    byte_min: core::arch::x86_64::__m128i,
+    carry: i16,
+) -> (core::arch::x86_64::__m256i, i16) {
+    use core::arch::x86_64::*;
+
+    let exc = _mm256_cvtepi8_epi16(byte_exc);
+    let mut sum = _mm256_add_epi16(exc, _mm256_slli_si256(exc, 2));
+    sum = _mm256_add_epi16(sum, _mm256_slli_si256(sum, 4));
+    sum = _mm256_add_epi16(sum, _mm256_slli_si256(sum, 8));
+    let low_total = _mm256_extract_epi16(sum, 7) as i16;
+    sum = _mm256_add_epi16(
+        sum,
+        _mm256_inserti128_si256(
+            _mm256_set1_epi16(carry),
+            _mm_set1_epi16(carry + low_total),
+            1,
+        ),
+    );
+    let total = _mm256_extract_epi16(sum, 15) as i16;
+    let mins = _mm256_sub_epi16(_mm256_sub_epi16(sum, exc), _mm256_cvtepi8_epi16(byte_min));
+    (mins, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// finds the minimum by walking all bits
+    fn naive(words: &[u64]) -> ExcessMin {
+        let mut result = ExcessMin {
+            min: 0,
+            idx: 0,
+            total: 0,
+        };
+        for (i, word) in words.iter().enumerate() {
+            for bit in 0..64 {
+                result.total += if (word >> bit) & 1 == 1 { 1 } else { -1 };
+                if result.total < result.min {
+                    result.min = result.total;
+                    result.idx = i * 64 + bit + 1;
+                }
+            }
+        }
+        result
+    }
+
+    /// words that mostly go down or up, to reach the extreme excess values
+    fn skewed_word() -> impl Strategy<Value = u64> {
+        prop_oneof![
+            any::<u64>(),
+            (any::<u64>(), any::<u64>()).prop_map(|(a, b)| a & b),
+            (any::<u64>(), any::<u64>()).prop_map(|(a, b)| a | b),
+            Just(0),
+            Just(u64::MAX),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn word_backends_agree(word in skewed_word()) {
+            let expected = naive(&[word]);
+            prop_assert_eq!(word_excess_min_lookup(word), expected);
+            prop_assert_eq!(word_excess_min_swar(word), expected);
+            #[cfg(target_arch = "x86_64")]
+            if ExcessBackend::Sse41.is_supported() {
+                prop_assert_eq!(unsafe { word_excess_min_sse41(word) }, expected);
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn block_backends_agree(block in prop::array::uniform4(skewed_word())) {
+            let expected = naive(&block);
+            for &backend in ExcessBackend::ALL {
+                if backend.is_supported() {
+                    prop_assert_eq!(block_excess_min(backend, &block), expected);
+                }
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn excess_rmq_with_every_backend(
+            bits in prop::collection::vec(prop::bool::weighted(0.4), 1..5000),
+            start in any::<prop::sample::Index>(),
+            end in any::<prop::sample::Index>()
+        ) {
+            use crate::bp::{AsBpRef, BitVec64, BpBitVec};
+            use crate::layout;
+
+            // the excess of a balanced parentheses sequence never drops below zero
+            let mut excess = 0;
+            let bv: BitVec64 = bits
+                .iter()
+                .map(|&open| {
+                    let open = open || excess == 0;
+                    excess += if open { 1 } else { -1 };
+                    open
+                })
+                .collect();
+            // the small blocks of the fast layout cover several superblocks
+            let bp_vec = BpBitVec::<layout::Fast>::from_bitvec(bv);
+            let bp = bp_vec.as_bp_ref();
+            let (start, end) = (start.index(bits.len() + 1), end.index(bits.len() + 1));
+            let range = start.min(end)..=start.max(end);
+            let expected = range
+                .clone()
+                .map(|pos| (bp.excess(pos), pos))
+                .min()
+                .map(|(excess, pos)| (pos, excess));
+
+            for &backend in ExcessBackend::ALL {
+                if backend.is_supported() {
+                    prop_assert_eq!(Some(bp.excess_rmq_with(backend, range.clone())), expected);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use super::excess_tables::{FWD_EXC, FWD_MIN};
+use super::BpRef;
+use crate::layout::Layout;
+
+impl<L: Layout> BpRef<'_, L> {
+    /// returns the smallest `p >= from` with `excess(p) <= target`
+    pub fn fwd_search(&self, from: usize, target: isize) -> Option<usize> {
+        if from > self.len() {
+            return None;
+        }
+        let mut excess = self.excess(from);
+        if excess <= target {
+            return Some(from);
+        }
+
+        // search in the rest of the word
+        let word_idx = from / 64;
+        let word_end = self.len().min((word_idx + 1) * 64);
+        for pos in from..word_end {
+            excess += if self.bit(pos) { 1 } else { -1 };
+            if excess <= target {
+                return Some(pos + 1);
+            }
+        }
+
+        // search in the rest of the block
+        let block = word_idx / L::BLOCK_WORDS;
+        let block_end = self.bv.len().min((block + 1) * L::BLOCK_WORDS);
+        for word in word_idx + 1..block_end {
+            if let Some(pos) = self.fwd_search_in_word(word, &mut excess, target) {
+                return Some(pos);
+            }
+        }
+
+        // search in the rest of the superblock
+        let superblock = block / L::SUPERBLOCK_BLOCKS;
+        if let Some(pos) = self.fwd_search_in_superblock(block + 1, superblock, target) {
+            return Some(pos);
+        }
+
+        // search the first superblock reaching the target in the min tree
+        let superblock = self.find_first_superblock(superblock + 1, target)?;
+        self.fwd_search_in_superblock(superblock * L::SUPERBLOCK_BLOCKS, superblock, target)
+    }
+
+    /// returns the largest `p <= to` with `excess(p) <= target`
+    pub fn bwd_search(&self, to: usize, target: isize) -> Option<usize> {
+        if to > self.len() {
+            return None;
+        }
+        let mut excess = self.excess(to);
+        if excess <= target {
+            return Some(to);
+        }
+
+        // search in the start of the word
+        let word_idx = to / 64;
+        for pos in (word_idx * 64..to).rev() {
+            excess -= if self.bit(pos) { 1 } else { -1 };
+            if excess <= target {
+                return Some(pos);
+            }
+        }
+
+        // search in the start of the block
+        let block = word_idx / L::BLOCK_WORDS;
+        for word in (block * L::BLOCK_WORDS..word_idx).rev() {
+            if let Some(pos) = self.bwd_search_in_word(word, &mut excess, target) {
+                return Some(pos);
+            }
+        }
+
+        // search in the start of the superblock
+        let superblock = block / L::SUPERBLOCK_BLOCKS;
+        if let Some(pos) = self.bwd_search_in_superblock(block, superblock, target) {
+            return Some(pos);
+        }
+
+        // search the last superblock reaching the target in the min tree
+        let superblock = self.find_last_superblock(superblock.checked_sub(1)?, target)?;
+        self.bwd_search_in_superblock((superblock + 1) * L::SUPERBLOCK_BLOCKS, superblock, target)
+    }
+}
+
+impl<L: Layout> BpRef<'_, L> {
+    fn block_min_excess(&self, block: usize, superblock_excess: isize) -> isize {
+        superblock_excess + self.block_excess_min[block] as isize
+    }
+
+    /// searches forward in the blocks `start..` of `superblock`
+    fn fwd_search_in_superblock(
+        &self,
+        start: usize,
+        superblock: usize,
+        target: isize,
+    ) -> Option<usize> {
+        let superblock_start = superblock * L::SUPERBLOCK_BLOCKS;
+        let end = self
+            .block_excess_min
+            .len()
+            .min(superblock_start + L::SUPERBLOCK_BLOCKS);
+        let superblock_excess = self.get_block_excess(superblock_start as u64);
+        let block =
+            (start..end).find(|&b| self.block_min_excess(b, superblock_excess) <= target)?;
+
+        let mut excess = self.get_block_excess(block as u64);
+        let block_end = self.bv.len().min((block + 1) * L::BLOCK_WORDS);
+        (block * L::BLOCK_WORDS..block_end)
+            .find_map(|word| self.fwd_search_in_word(word, &mut excess, target))
+    }
+
+    /// searches backward in the blocks `..end` of `superblock`
+    fn bwd_search_in_superblock(
+        &self,
+        end: usize,
+        superblock: usize,
+        target: isize,
+    ) -> Option<usize> {
+        let superblock_start = superblock * L::SUPERBLOCK_BLOCKS;
+        let superblock_excess = self.get_block_excess(superblock_start as u64);
+        let block = (superblock_start..end)
+            .rev()
+            .find(|&b| self.block_min_excess(b, superblock_excess) <= target)?;
+
+        // the block ends where the next one starts, which is
+        // before the end of the bit vector
+        let mut excess = self.get_block_excess(block as u64 + 1);
+        (block * L::BLOCK_WORDS..(block + 1) * L::BLOCK_WORDS)
+            .rev()
+            .find_map(|word| self.bwd_search_in_word(word, &mut excess, target))
+    }
+
+    fn fwd_search_in_word(
+        &self,
+        word_idx: usize,
+        excess: &mut isize,
+        target: isize,
+    ) -> Option<usize> {
+        let word = self.bit_word(word_idx as u64);
+        let word_start = word_idx * 64;
+        let n_bits = 64.min(self.len() - word_start);
+        for byte_start in (0..n_bits).step_by(8) {
+            let byte = ((word >> byte_start) & 0xFF) as usize;
+            if byte_start + 8 <= n_bits && *excess - (FWD_MIN[byte] as isize) > target {
+                *excess += FWD_EXC[byte] as isize;
+                continue;
+            }
+            for bit in byte_start..n_bits.min(byte_start + 8) {
+                *excess += if (word >> bit) & 1 == 1 { 1 } else { -1 };
+                if *excess <= target {
+                    return Some(word_start + bit + 1);
+                }
+            }
+        }
+        None
+    }
+
+    /// `word_idx` has to be a full word before the end of the bit vector
+    fn bwd_search_in_word(
+        &self,
+        word_idx: usize,
+        excess: &mut isize,
+        target: isize,
+    ) -> Option<usize> {
+        let word = self.bit_word(word_idx as u64);
+        let word_start = word_idx * 64;
+        for byte_start in (0..64).step_by(8).rev() {
+            let byte = ((word >> byte_start) & 0xFF) as usize;
+            let byte_start_excess = *excess - FWD_EXC[byte] as isize;
+            if byte_start_excess - (FWD_MIN[byte] as isize) > target {
+                *excess = byte_start_excess;
+                continue;
+            }
+            for bit in (byte_start..byte_start + 8).rev() {
+                *excess -= if (word >> bit) & 1 == 1 { 1 } else { -1 };
+                if *excess <= target {
+                    return Some(word_start + bit);
+                }
+            }
+        }
+        None
+    }
+
+    /// returns the first superblock `>= start` whose minimum excess is at most `target`
+    fn find_first_superblock(&self, start: usize, target: isize) -> Option<usize> {
+        let internal_nodes = self.internal_nodes as usize;
+        let treesize = self.superblock_excess_min.len();
+        let mut node = internal_nodes + start;
+        if node >= treesize {
+            return None;
+        }
+        while self.superblock_min(node as u64) > target {
+            // move to the next subtree on the right
+            while node & 1 == 1 {
+                node /= 2;
+            }
+            if node == 0 {
+                return None;
+            }
+            node += 1;
+            if node >= treesize {
+                return None;
+            }
+        }
+        while node < internal_nodes {
+            node *= 2;
+            if self.superblock_min(node as u64) > target {
+                node += 1;
+            }
+        }
+        Some(node - internal_nodes)
+    }
+
+    /// returns the last superblock `<= end` whose minimum excess is at most `target`
+    fn find_last_superblock(&self, end: usize, target: isize) -> Option<usize> {
+        let internal_nodes = self.internal_nodes as usize;
+        let treesize = self.superblock_excess_min.len();
+        let mut node = internal_nodes + end;
+        while self.superblock_min(node as u64) > target {
+            // move to the next subtree on the left
+            while node & 1 == 0 {
+                node /= 2;
+            }
+            if node == 1 {
+                return None;
+            }
+            node -= 1;
+        }
+        while node < internal_nodes {
+            node = 2 * node + 1;
+            if node >= treesize || self.superblock_min(node as u64) > target {
+                node -= 1;
+            }
+        }
+        Some(node - internal_nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::bp::{balanced, AsBpRef, BitVec64, BpBitVec};
+    use crate::layout::{self, Layout};
+
+    fn prefix_excess(bv: &BitVec64) -> Vec<isize> {
+        let mut excess = vec![0];
+        for bit in bv.iter() {
+            excess.push(excess.last().unwrap() + if *bit { 1 } else { -1 });
+        }
+        excess
+    }
+
+    fn searches<L: Layout>(bv: &BitVec64, pos: usize, target: isize) -> [Option<usize>; 2] {
+        let bp_vec = BpBitVec::<L>::from_bitvec(bv.clone());
+        let bp = bp_vec.as_bp_ref();
+        [bp.fwd_search(pos, target), bp.bwd_search(pos, target)]
+    }
+
+    proptest! {
+        #[test]
+        fn fwd_bwd_search(
+            steps in prop::collection::vec(any::<bool>(), 1..20000),
+            pos in any::<prop::sample::Index>(),
+            delta in 0isize..200
+        ) {
+            let bv = balanced(&steps);
+            let excess = prefix_excess(&bv);
+            let pos = pos.index(bv.len() + 1);
+            let target = excess[pos] - delta;
+
+            let expected_fwd = (pos..=bv.len()).find(|&p| excess[p] <= target);
+            let expected_bwd = (0..=pos).rev().find(|&p| excess[p] <= target);
+            let expected = [expected_fwd, expected_bwd];
+            prop_assert_eq!(searches::<layout::Compact>(&bv, pos, target), expected);
+            prop_assert_eq!(searches::<layout::Balanced>(&bv, pos, target), expected);
+            prop_assert_eq!(searches::<layout::Fast>(&bv, pos, target), expected);
+        }
+    }
+}
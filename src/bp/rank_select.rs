@@ -1,13 +1,16 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
 use super::BitVec64;
-use super::BpBitVec;
+use super::BpRef;
+use crate::layout::Layout;
 
-pub const BLOCK_SIZE: usize = 8; // in 64bit words
-const SELECT_ONES_PER_HINT: usize = 64 * BLOCK_SIZE * 2; // must be > block_size * 64
-const SELECT_ZEROS_PER_HINT: usize = SELECT_ONES_PER_HINT;
+pub const BLOCK_SIZE: usize = 8; // in 64bit words, fixed by the 9 bit sub-block ranks
 
-impl BpBitVec {
+impl<L: Layout> BpRef<'_, L> {
     pub fn num_ones(&self) -> u64 {
-        self.block_rank_pairs.last().unwrap() - 2
+        // the rank of the past-the-end block is stored in front of its sub-block ranks
+        self.block_rank_pairs[self.block_rank_pairs.len() - 2]
     }
 
     pub fn rank1(&self, offset: u64) -> u64 {
@@ -26,11 +29,11 @@ impl BpBitVec {
     pub fn select0(&self, offset: usize) -> usize {
         let offset = offset as u64;
         let mut a = 0;
-        let chunk = offset as usize / SELECT_ZEROS_PER_HINT;
+        let chunk = offset as usize / L::ZEROS_PER_HINT;
         if chunk != 0 {
             a = self.select0_hints[chunk - 1];
         }
-        let mut b = self.select0_hints[chunk] as u64 + 1;
+        let mut b = self.select0_hints[chunk] + 1;
 
         while b - a > 1 {
             let mid = a + (b - a) / 2;
@@ -46,8 +49,7 @@ impl BpBitVec {
         let block_offset = block * BLOCK_SIZE as u64;
         let mut cur_rank0 = self.block_rank0(block);
 
-        let rank_in_block_parallel =
-            ((offset - cur_rank0) as u64 * crate::util::ONES_STEP_9) as u64;
+        let rank_in_block_parallel = (offset - cur_rank0) * crate::util::ONES_STEP_9;
         let sub_ranks = 64 * crate::util::INV_COUNT_STEP_9 - self.sub_block_ranks(block);
         let sub_block_offset = (crate::util::uleq_step_9(sub_ranks, rank_in_block_parallel)
             .wrapping_mul(crate::util::ONES_STEP_9))
@@ -62,16 +64,52 @@ impl BpBitVec {
         ret as usize
     }
 
+    /// unlike `select0` there are no hints, so the block is found
+    /// by a binary search over all blocks
+    pub fn select1(&self, offset: usize) -> usize {
+        let offset = offset as u64;
+        let mut a = 0;
+        let mut b = (self.block_rank_pairs.len() / 2 - 1) as u64;
+
+        while b - a > 1 {
+            let mid = a + (b - a) / 2;
+            let x = self.block_rank1(mid);
+            if x <= offset {
+                a = mid;
+            } else {
+                b = mid;
+            }
+        }
+        let block = a;
+
+        let block_offset = block * BLOCK_SIZE as u64;
+        let mut cur_rank = self.block_rank1(block);
+
+        let rank_in_block_parallel = (offset - cur_rank) * crate::util::ONES_STEP_9;
+        let sub_ranks = self.sub_block_ranks(block);
+        let sub_block_offset = (crate::util::uleq_step_9(sub_ranks, rank_in_block_parallel)
+            .wrapping_mul(crate::util::ONES_STEP_9))
+            >> 54
+            & 0x7;
+
+        cur_rank += sub_ranks >> ((7 - sub_block_offset) * 9) & 0x1FF;
+
+        let word_offset = block_offset + sub_block_offset;
+        let ret = word_offset * 64
+            + crate::util::select1_in_u64(self.bit_word(word_offset), offset - cur_rank);
+        ret as usize
+    }
+
     fn block_rank1(&self, block: u64) -> u64 {
-        self.block_rank_pairs[block as usize * 2] as u64
+        self.block_rank_pairs[block as usize * 2]
     }
 
     fn block_rank0(&self, block: u64) -> u64 {
-        block * BLOCK_SIZE as u64 * 64 - self.block_rank_pairs[block as usize * 2] as u64
+        block * BLOCK_SIZE as u64 * 64 - self.block_rank_pairs[block as usize * 2]
     }
 
     fn sub_block_ranks(&self, sub_block: u64) -> u64 {
-        self.block_rank_pairs[sub_block as usize * 2 + 1] as u64
+        self.block_rank_pairs[sub_block as usize * 2 + 1]
     }
 
     pub(crate) fn sub_block_rank(&self, sub_block: u64) -> u64 {
@@ -84,66 +122,124 @@ impl BpBitVec {
     }
 }
 
+impl<L: Layout> BpRef<'_, L> {
+    /// checks that the rank pairs are the ones built from the bits,
+    /// the number of pairs has to be checked first
+    pub(crate) fn has_valid_rank_pairs(&self) -> bool {
+        let mut rank = 0u64;
+        for (block, words) in self.bv.chunks(BLOCK_SIZE).enumerate() {
+            let (subranks, ones) = block_ranks(words);
+            if self.block_rank_pairs[2 * block] != rank
+                || self.block_rank_pairs[2 * block + 1] != subranks
+            {
+                return false;
+            }
+            rank += ones;
+        }
+        self.block_rank_pairs[self.block_rank_pairs.len() - 2..] == [rank, 0]
+    }
+
+    /// checks that the hints are the ones built from valid rank pairs
+    pub(crate) fn has_valid_select0_hints(&self) -> bool {
+        let num_blocks = self.block_rank_pairs.len() / 2 - 1;
+        (0..num_blocks)
+            .filter(|&i| has_select0_hint::<L>(self.block_rank_pairs, i))
+            .map(|i| i as u64)
+            .chain(core::iter::once(num_blocks as u64))
+            .eq(self.select0_hints.iter().copied())
+    }
+}
+
 pub fn build_rank_pairs(bitvec: &BitVec64) -> Vec<u64> {
-    let mut block_rank_pairs = Vec::new();
-    let mut next_rank = 0u64;
-    let mut cur_subrank = 0u64;
+    let blocks = bitvec.as_raw_slice().chunks(BLOCK_SIZE).map(block_ranks);
+    rank_pairs_from_blocks(blocks)
+}
+
+#[cfg(feature = "rayon")]
+pub fn par_build_rank_pairs(bitvec: &BitVec64) -> Vec<u64> {
+    use rayon::prelude::*;
+
+    let blocks: Vec<(u64, u64)> = bitvec
+        .as_raw_slice()
+        .par_chunks(BLOCK_SIZE)
+        .map(block_ranks)
+        .collect();
+    rank_pairs_from_blocks(blocks)
+}
+
+/// returns the sub-block ranks and the number of ones of the words of a block,
+/// the ranks of missing words in the last block are the number of ones
+fn block_ranks(words: &[u64]) -> (u64, u64) {
     let mut subranks = 0u64;
-    block_rank_pairs.push(0);
-    let words = bitvec.as_raw_slice();
-    for (i, &word) in words.iter().enumerate() {
-        let word_pop = word.count_ones();
-        let shift = i % BLOCK_SIZE;
-        if shift != 0 {
+    let mut cur_subrank = 0u64;
+    for i in 0..BLOCK_SIZE {
+        if i != 0 {
             subranks <<= 9;
             subranks |= cur_subrank;
         }
-        next_rank += word_pop as u64;
-        cur_subrank += word_pop as u64;
-
-        if shift == BLOCK_SIZE - 1 {
-            block_rank_pairs.push(subranks);
-            block_rank_pairs.push(next_rank);
-            subranks = 0;
-            cur_subrank = 0;
-        }
-    }
-    let left = BLOCK_SIZE - words.len() % BLOCK_SIZE;
-    for _i in 0..left {
-        subranks <<= 9;
-        subranks |= cur_subrank;
+        cur_subrank += words.get(i).map_or(0, |word| word.count_ones() as u64);
     }
-    block_rank_pairs.push(subranks);
+    (subranks, cur_subrank)
+}
 
-    if words.len() % BLOCK_SIZE != 0 {
+/// interleaves the rank in front of every block with its sub-block ranks,
+/// followed by the rank of the past-the-end block
+fn rank_pairs_from_blocks(blocks: impl IntoIterator<Item = (u64, u64)>) -> Vec<u64> {
+    let mut block_rank_pairs = vec![0];
+    let mut next_rank = 0u64;
+    for (subranks, ones) in blocks {
+        next_rank += ones;
+        block_rank_pairs.push(subranks);
         block_rank_pairs.push(next_rank);
-        block_rank_pairs.push(0);
     }
+    block_rank_pairs.push(0);
     block_rank_pairs
 }
 
-pub fn build_select0_hints(block_rank_pairs: &[u64]) -> Vec<u64> {
+pub fn build_select0_hints<L: Layout>(block_rank_pairs: &[u64]) -> Vec<u64> {
     let num_blocks = block_rank_pairs.len() / 2 - 1;
-    let mut select0_hints = Vec::new();
-    let mut cur_zeros_threshold = SELECT_ZEROS_PER_HINT as u64;
-    for i in 0..num_blocks {
-        let block = i as u64 + 1;
-        let block_rank0 =
-            block * BLOCK_SIZE as u64 * 64 - block_rank_pairs[block as usize * 2] as u64;
-        if block_rank0 > cur_zeros_threshold {
-            select0_hints.push(i as u64);
-            cur_zeros_threshold += SELECT_ZEROS_PER_HINT as u64;
-        }
-    }
+    (0..num_blocks)
+        .filter(|&i| has_select0_hint::<L>(block_rank_pairs, i))
+        .map(|i| i as u64)
+        .chain(core::iter::once(num_blocks as u64))
+        .collect()
+}
+
+#[cfg(feature = "rayon")]
+pub fn par_build_select0_hints<L: Layout>(block_rank_pairs: &[u64]) -> Vec<u64> {
+    use rayon::prelude::*;
+
+    let num_blocks = block_rank_pairs.len() / 2 - 1;
+    let mut select0_hints: Vec<u64> = (0..num_blocks)
+        .into_par_iter()
+        .filter(|&i| has_select0_hint::<L>(block_rank_pairs, i))
+        .map(|i| i as u64)
+        .collect();
     select0_hints.push(num_blocks as u64);
     select0_hints
 }
 
+/// a hint points to the block in which the number of zeros passes a multiple
+/// of `L::ZEROS_PER_HINT`, blocks are too short to pass two of them
+fn has_select0_hint<L: Layout>(block_rank_pairs: &[u64], block: usize) -> bool {
+    let block_rank0 = |block: usize| (block * BLOCK_SIZE * 64) as u64 - block_rank_pairs[block * 2];
+    let zeros_per_hint = L::ZEROS_PER_HINT as u64;
+    let threshold = block_rank0(block).div_ceil(zeros_per_hint).max(1) * zeros_per_hint;
+    block_rank0(block + 1) > threshold
+}
+
 #[cfg(test)]
 mod tests {
     use proptest::prelude::*;
 
-    use crate::bp::BitVec64;
+    use crate::bp::{AsBpRef, BitVec64, BpBitVec};
+    use crate::layout::{self, Layout};
+
+    fn select0_all<L: Layout>(bv: &BitVec64, count: usize) -> Vec<usize> {
+        let bp_vec = BpBitVec::<L>::from_bitvec(bv.clone());
+        let bp_vec = bp_vec.as_bp_ref();
+        (0..count).map(|offset| bp_vec.select0(offset)).collect()
+    }
 
     proptest! {
         #[test]
@@ -158,13 +254,34 @@ mod tests {
                 bv.set(*one_pos as usize, false);
             }
 
-            let bp_vec = super::BpBitVec::from_bitvec(bv);
+            let expected: Vec<usize> = zero_positions.iter().map(|&pos| pos as usize).collect();
+            assert_eq!(select0_all::<layout::Compact>(&bv, expected.len()),expected);
+            assert_eq!(select0_all::<layout::Balanced>(&bv, expected.len()),expected);
+            assert_eq!(select0_all::<layout::Fast>(&bv, expected.len()),expected);
 
-            for (offset,pos) in zero_positions.into_iter().enumerate() {
-                let actual = bp_vec.select0(offset);
-                assert_eq!(pos as usize,actual);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn select1(
+            one_positions in prop::collection::btree_set(any::<u16>(), 1..1000)
+        ) {
+            let mut bv = BitVec64::new();
+            bv.resize(*one_positions.last().unwrap() as usize + 1, false);
+
+            for one_pos in &one_positions {
+                bv.set(*one_pos as usize, true);
             }
 
+            let bp_vec: BpBitVec = BpBitVec::from_bitvec(bv);
+            let bp_vec = bp_vec.as_bp_ref();
+
+            assert_eq!(bp_vec.num_ones() as usize,one_positions.len());
+            for (offset,pos) in one_positions.into_iter().enumerate() {
+                let actual = bp_vec.select1(offset);
+                assert_eq!(pos as usize,actual);
+            }
         }
     }
 
@@ -180,7 +297,8 @@ mod tests {
                 bv.set(*one_pos as usize, true);
             }
 
-            let bp_vec = super::BpBitVec::from_bitvec(bv);
+            let bp_vec: BpBitVec = BpBitVec::from_bitvec(bv);
+            let bp_vec = bp_vec.as_bp_ref();
 
             for (offset,pos) in one_positions.into_iter().enumerate() {
                 let actual = bp_vec.rank1(pos as u64);
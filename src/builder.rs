@@ -0,0 +1,165 @@
+use core::marker::PhantomData;
+
+use crate::cartesian_tree::{CartesianTree, CartesianTreeBuilder};
+use crate::layout::{self, Layout};
+use crate::{Rmq, TieBreak};
+
+/// Builds an [`Rmq`] incrementally from values that arrive one at a time
+///
+/// Only the values on the right spine of the Cartesian tree are kept in
+/// memory, so the input never has to be materialized as a whole.
+///
+/// ```rust
+/// use range_minimum_query::RmqBuilder;
+///
+/// let mut builder = RmqBuilder::new();
+/// builder.push(4);
+/// builder.extend([2, 3]);
+/// builder.extend([1, 5]);
+/// assert_eq!(builder.len(), 5);
+///
+/// let rmq = builder.finish();
+/// assert_eq!(rmq.range_minimum(0..3), Some(1));
+/// ```
+///
+/// The index is built in the [`Layout`] `L`, selected with [`RmqBuilder::with_layout`]:
+///
+/// ```rust
+/// use range_minimum_query::{layout, Rmq, RmqBuilder};
+///
+/// let mut builder = RmqBuilder::new().with_layout::<layout::Fast>();
+/// builder.extend([4, 2, 3, 1, 5]);
+/// let rmq: Rmq<layout::Fast> = builder.finish();
+/// assert_eq!(rmq.range_minimum(0..3), Some(1));
+/// ```
+#[derive(Debug)]
+pub struct RmqBuilder<T, L = layout::Balanced> {
+    builder: CartesianTreeBuilder<T>,
+    len: usize,
+    layout: PhantomData<L>,
+}
+
+impl<T: Ord> RmqBuilder<T> {
+    /// creates an empty builder
+    pub fn new() -> Self {
+        Self {
+            builder: CartesianTree::builder(),
+            len: 0,
+            layout: PhantomData,
+        }
+    }
+
+    /// creates an empty builder with space reserved for `capacity` values
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            builder: CartesianTreeBuilder::with_capacity(capacity),
+            len: 0,
+            layout: PhantomData,
+        }
+    }
+}
+
+impl<T: Ord, L: Layout> RmqBuilder<T, L> {
+    /// selects the layout of the built index, its auxiliary
+    /// structures are only built once by [`RmqBuilder::finish`]
+    pub fn with_layout<M: Layout>(self) -> RmqBuilder<T, M> {
+        RmqBuilder {
+            builder: self.builder,
+            len: self.len,
+            layout: PhantomData,
+        }
+    }
+
+    /// selects which position of a repeated minimum is reported by the queries
+    pub fn with_tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.builder = self.builder.with_tie_break(tie_break);
+        self
+    }
+
+    /// appends `value` at the next position
+    pub fn push(&mut self, value: T) {
+        self.builder.push(value);
+        self.len += 1;
+    }
+
+    /// returns the number of values pushed so far
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// returns `true` if no values have been pushed
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// builds the index over all pushed values
+    pub fn finish(self) -> Rmq<L> {
+        Rmq {
+            cartesian_tree: self.builder.build(),
+        }
+    }
+}
+
+impl<T: Ord> Default for RmqBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord, L: Layout> Extend<T> for RmqBuilder<T, L> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::RmqBuilder;
+    use crate::layout;
+
+    proptest! {
+        #[test]
+        fn chunked_build_matches_from_iter(
+            chunks in prop::collection::vec(prop::collection::vec(any::<u16>(), 0..50), 1..40),
+            start in 0usize..1000,
+            len in 1usize..100
+        ) {
+            let elems: Vec<u16> = chunks.iter().flatten().copied().collect();
+            prop_assume!(start + len <= elems.len());
+
+            let mut builder = RmqBuilder::with_capacity(elems.len());
+            for chunk in &chunks {
+                builder.extend(chunk.iter().copied());
+            }
+            assert_eq!(builder.len(),elems.len());
+            let rmq = builder.finish();
+
+            let expected = super::Rmq::from_iter(elems.iter());
+            assert_eq!(rmq.len(),elems.len());
+            assert_eq!(rmq.range_minimum(start..(start+len)),expected.range_minimum(start..(start+len)));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn layout_build_matches_conversion(
+            elems in prop::collection::vec(any::<u16>(), 0..3000),
+            start in 0usize..3000,
+            len in 1usize..1000
+        ) {
+            let mut builder = RmqBuilder::new().with_layout::<layout::Fast>();
+            builder.extend(elems.iter().copied());
+            let rmq = builder.finish();
+
+            let expected: super::Rmq<layout::Fast> = super::Rmq::from_iter(elems.iter()).into_layout();
+            prop_assert_eq!(rmq.space_breakdown(), expected.space_breakdown());
+            prop_assert_eq!(rmq.to_compact_bytes(), expected.to_compact_bytes());
+            let range = start..(start + len);
+            prop_assert_eq!(rmq.range_minimum(range.clone()), expected.range_minimum(range));
+        }
+    }
+}
@@ -1,67 +1,319 @@
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::marker::PhantomData;
+
+use crate::bp::AsBpRef;
 use crate::bp::BitVec64;
 use crate::bp::BpBitVec;
+use crate::bp::SpaceBreakdown;
+use crate::layout::Layout;
+use crate::RmqError;
+
+mod backward;
+#[cfg(feature = "rayon")]
+mod parallel;
+
+use self::backward::BackwardBits;
+pub(crate) use self::backward::BitSink;
 
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-
-pub struct CartesianTree {
-    bp: BpBitVec,
+pub struct CartesianTree<B = BpBitVec> {
+    pub(crate) bp: B,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) tie_break: TieBreak,
 }
 
 impl CartesianTree {
-    fn builder<T: Ord>() -> CartesianTreeBuilder<T> {
+    pub fn builder<T>() -> CartesianTreeBuilder<T> {
         CartesianTreeBuilder::new()
     }
+}
 
-    fn len(&self) -> usize {
-        self.bp.len() / 2 - 1
+impl<L: Layout> CartesianTree<BpBitVec<L>> {
+    /// builds the tree using `compare` instead of `Ord`, the root of
+    /// every subtree is the element that compares smallest
+    pub fn from_iter_by<T, I, F>(iter: I, mut compare: F, tie_break: TieBreak) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        F: FnMut(&T, &T) -> core::cmp::Ordering,
+    {
+        let mut builder = CartesianTree::builder().with_tie_break(tie_break);
+        for item in iter {
+            builder.push_by(item, &mut compare);
+        }
+        builder.build()
     }
 
-    pub fn range_minimum(&self, range: impl std::ops::RangeBounds<usize>) -> Option<usize> {
-        let range_start = match range.start_bound() {
-            std::ops::Bound::Included(t) => *t,
-            std::ops::Bound::Excluded(t) => *t + 1,
-            std::ops::Bound::Unbounded => 0,
-        };
-        let range_end = match range.end_bound() {
-            std::ops::Bound::Included(t) => *t,
-            std::ops::Bound::Excluded(t) => *t - 1,
-            std::ops::Bound::Unbounded => self.len() - 1,
-        };
-        let range = range_start..=range_end;
+    /// builds the tree of the elements of an iterator of known length, writing every
+    /// parenthesis to its final position instead of reversing the sequence afterwards
+    ///
+    /// # Panics
+    ///
+    /// if the iterator yields a different number of elements than its length
+    pub fn from_exact_size_iter_by<T, I, F>(iter: I, mut compare: F, tie_break: TieBreak) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+        F: FnMut(&T, &T) -> core::cmp::Ordering,
+    {
+        let mut iter = iter.into_iter();
+        let len = iter.len();
+        let n_bits = 2 * len + 2;
+        let mut words = alloc::vec![0; n_bits.div_ceil(64)];
+        let mut builder = CartesianTreeBuilder::with_backward_bits(len, |i, word| words[i] = word)
+            .with_tie_break(tie_break);
+        for item in iter.by_ref().take(len) {
+            builder.push_by(item, &mut compare);
+        }
+        assert!(
+            builder.finish_backward() && iter.next().is_none(),
+            "the iterator yielded a different number of elements than its length"
+        );
 
-        if range.is_empty() {
-            return None;
+        let mut bv = BitVec64::from_vec(words);
+        bv.truncate(n_bits);
+        CartesianTree {
+            bp: BpBitVec::from_bitvec(bv),
+            tie_break,
+        }
+    }
+
+    /// builds the tree of `values`, keeping positions instead of values on the
+    /// stack of the construction, as `u32` if there are fewer than `2^32` values
+    pub fn from_slice_by<T, F>(values: &[T], mut compare: F, tie_break: TieBreak) -> Self
+    where
+        F: FnMut(&T, &T) -> core::cmp::Ordering,
+    {
+        match u32::try_from(values.len()) {
+            Ok(len) => Self::from_exact_size_iter_by(
+                0..len,
+                |&a, &b| compare(&values[a as usize], &values[b as usize]),
+                tie_break,
+            ),
+            Err(_) => Self::from_exact_size_iter_by(
+                0..values.len(),
+                |&a, &b| compare(&values[a], &values[b]),
+                tie_break,
+            ),
         }
+    }
+
+    pub fn space_breakdown(&self) -> SpaceBreakdown {
+        self.bp.space_breakdown()
+    }
+
+    /// rebuilds the auxiliary structures of the same tree in layout `M`
+    pub fn into_layout<M: Layout>(self) -> CartesianTree<BpBitVec<M>> {
+        CartesianTree {
+            bp: self.bp.into_layout(),
+            tie_break: self.tie_break,
+        }
+    }
+}
+
+impl<B: AsBpRef> CartesianTree<B> {
+    pub fn len(&self) -> usize {
+        self.bp.as_bp_ref().len() / 2 - 1
+    }
+
+    /// converts `range` into the inclusive range of positions it covers
+    pub fn resolve_range(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<core::ops::RangeInclusive<usize>, RmqError> {
+        crate::range_minimum::resolve_range(range, self.len())
+    }
+
+    pub fn range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        let range = self.resolve_range(range)?;
         if range.start() == range.end() {
-            return Some(*range.start());
+            return Ok(*range.start());
         }
+        let end = self.end_selects(*range.end());
+        Ok(self.minimum_between(self.start_select(*range.start()), end))
+    }
 
-        let length = self.len();
+    /// answers all `queries` into `out`, visiting the start positions and then
+    /// the end positions in sorted order so the `select0` results of a shared
+    /// start or end are computed once
+    pub fn range_minimum_batch(
+        &self,
+        queries: &[core::ops::Range<usize>],
+        out: &mut [usize],
+    ) -> Result<(), RmqError> {
+        assert_eq!(
+            queries.len(),
+            out.len(),
+            "every query needs a position in the output"
+        );
+        let mut by_start = queries
+            .iter()
+            .enumerate()
+            .map(|(q, query)| {
+                let range = self.resolve_range(query.clone())?;
+                Ok((*range.start(), *range.end(), q))
+            })
+            .collect::<Result<Vec<_>, RmqError>>()?;
+        by_start.sort_unstable();
 
-        let t = self.bp.select0(length - range.end() - 1);
-        let exc_t = (t - 2 * (length - range.end() - 1)) as isize;
+        let mut by_end = Vec::with_capacity(by_start.len());
+        let mut start: Option<(usize, usize)> = None;
+        for (range_start, range_end, q) in by_start {
+            if range_start == range_end {
+                out[q] = range_start;
+                continue;
+            }
+            let y = match start {
+                Some((pos, y)) if pos == range_start => y,
+                _ => {
+                    let y = self.start_select(range_start);
+                    start = Some((range_start, y));
+                    y
+                }
+            };
+            by_end.push((range_end, y, q));
+        }
+        by_end.sort_unstable();
 
-        assert!(exc_t - 1 == self.bp.excess(t + 1));
+        let mut end: Option<EndSelects> = None;
+        for (range_end, y, q) in by_end {
+            let selects = match end {
+                Some(selects) if selects.end == range_end => selects,
+                _ => *end.insert(self.end_selects(range_end)),
+            };
+            out[q] = self.minimum_between(y, selects);
+        }
+        Ok(())
+    }
 
-        let x = self.bp.select0(length - range.end());
-        let y = self.bp.select0(length - range.start());
+    fn start_select(&self, start: usize) -> usize {
+        self.bp.as_bp_ref().select0(self.len() - start)
+    }
 
-        let (w, exc_w) = self.bp.excess_rmq(x..=y);
+    fn end_selects(&self, end: usize) -> EndSelects {
+        let bp = self.bp.as_bp_ref();
+        let rank = self.len() - end - 1;
+        let t = bp.select0(rank);
+        let exc_t = (t - 2 * rank) as isize;
+        debug_assert_eq!(exc_t - 1, bp.excess(t + 1));
+        EndSelects {
+            end,
+            exc_t,
+            x: bp.select0(rank + 1),
+        }
+    }
 
+    /// returns the minimum of `start..=end` for `start < end`, given the
+    /// `select0` result `y` of `start`
+    fn minimum_between(&self, y: usize, end: EndSelects) -> usize {
+        let bp = self.bp.as_bp_ref();
+        let (w, exc_w) = bp.excess_rmq(end.x..=y);
         let rank0_w = (w - exc_w as usize) / 2;
 
-        if exc_w >= exc_t - 1 {
-            Some(*range.end())
+        if exc_w >= end.exc_t - 1 {
+            end.end
         } else {
-            Some(length - rank0_w)
+            self.len() - rank0_w
         }
     }
 }
 
-impl<T: Ord> FromIterator<T> for CartesianTree {
+/// the `select0` results needed for queries ending at `end`
+#[derive(Debug, Clone, Copy)]
+struct EndSelects {
+    end: usize,
+    exc_t: isize,
+    x: usize,
+}
+
+// The bit vector is the DFUDS encoding of the tree in which the parent of every
+// element is the closest element to its right that is preferred as a minimum,
+// its "next winner". Elements are listed right to left, so element `i` is described
+// by the parentheses following the `(len - i - 1)`-th close parenthesis.
+impl<B: AsBpRef> CartesianTree<B> {
+    fn node_start(&self, i: usize) -> usize {
+        self.bp.as_bp_ref().select0(self.len() - i - 1) + 1
+    }
+
+    /// returns the closest position right of `i` that is preferred over `i` as a minimum
+    pub fn next_winner(&self, i: usize) -> Option<usize> {
+        if i >= self.len() {
+            return None;
+        }
+        let bp = self.bp.as_bp_ref();
+        let start = self.node_start(i);
+        // the open parenthesis matching the close in front of the node lies in its parent
+        let open = bp.bwd_search(start - 1, bp.excess(start))?;
+        let rank0 = open - bp.rank1(open as u64) as usize;
+        (rank0 != 0).then(|| self.len() - rank0)
+    }
+
+    /// returns the closest position left of `i` that is preferred over `i` as a minimum
+    pub fn prev_winner(&self, i: usize) -> Option<usize> {
+        if i >= self.len() {
+            return None;
+        }
+        let bp = self.bp.as_bp_ref();
+        let start = self.node_start(i);
+        // the subtree of the node contains the positions `prev_winner + 1..=i`
+        let end = bp.fwd_search(start, bp.excess(start) - 1)?;
+        let rank0 = |pos: usize| pos - bp.rank1(pos as u64) as usize;
+        i.checked_sub(rank0(end) - rank0(start))
+    }
+
+    /// returns the maximal range in which `i` is the reported minimum
+    pub fn subtree_range(&self, i: usize) -> Option<core::ops::RangeInclusive<usize>> {
+        if i >= self.len() {
+            return None;
+        }
+        let start = self.prev_winner(i).map_or(0, |p| p + 1);
+        let end = self.next_winner(i).unwrap_or(self.len()) - 1;
+        Some(start..=end)
+    }
+
+    /// returns the parent of `i`, `None` for the root or if `i` is out of bounds
+    pub fn parent(&self, i: usize) -> Option<usize> {
+        let range = self.subtree_range(i)?;
+        let left = range.start().checked_sub(1);
+        let right = Some(range.end() + 1).filter(|&r| r < self.len());
+        match (left, right) {
+            (Some(left), Some(right)) => {
+                // the parent is the candidate that loses against the other
+                let winner = self.range_minimum(left..=right).ok()?;
+                Some(if winner == left { right } else { left })
+            }
+            (left, right) => left.or(right),
+        }
+    }
+
+    /// returns the left child of `i`, `None` for leaves and nodes
+    /// without one or if `i` is out of bounds
+    pub fn left_child(&self, i: usize) -> Option<usize> {
+        let range = self.subtree_range(i)?;
+        self.range_minimum(*range.start()..i).ok()
+    }
+
+    /// returns the right child of `i`, `None` for leaves and nodes
+    /// without one or if `i` is out of bounds
+    pub fn right_child(&self, i: usize) -> Option<usize> {
+        let range = self.subtree_range(i)?;
+        self.range_minimum(i + 1..=*range.end()).ok()
+    }
+
+    /// returns the lowest common ancestor of `i` and `j`, the minimum of `i..=j`,
+    /// `None` if either is out of bounds
+    pub fn lca(&self, i: usize, j: usize) -> Option<usize> {
+        self.range_minimum(i.min(j)..=i.max(j)).ok()
+    }
+}
+
+impl<T: Ord, L: Layout> FromIterator<T> for CartesianTree<BpBitVec<L>> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
-        let mut builder = Self::builder();
+        let mut builder = CartesianTree::builder();
         for item in iter {
             builder.push(item);
         }
@@ -69,39 +321,157 @@ impl<T: Ord> FromIterator<T> for CartesianTree {
     }
 }
 
-struct CartesianTreeBuilder<T> {
-    bp: BitVec64,
-    stack: Vec<T>,
+/// Selects which position is reported when the minimum of
+/// a range occurs more than once
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TieBreak {
+    /// report the leftmost position of the minimum
+    #[default]
+    Leftmost,
+    /// report the rightmost position of the minimum
+    Rightmost,
+}
+
+/// The stack of the construction, which holds the right spine of the tree
+pub(crate) trait Stack<T> {
+    type Error;
+
+    fn last(&mut self) -> Result<Option<&T>, Self::Error>;
+
+    /// removes the top of the stack, which has to be loaded by [`Stack::last`]
+    fn pop(&mut self);
+
+    fn push(&mut self, elem: T) -> Result<(), Self::Error>;
+}
+
+impl<T> Stack<T> for Vec<T> {
+    type Error = Infallible;
+
+    fn last(&mut self) -> Result<Option<&T>, Infallible> {
+        Ok(self.as_slice().last())
+    }
+
+    fn pop(&mut self) {
+        Vec::pop(self);
+    }
+
+    fn push(&mut self, elem: T) -> Result<(), Infallible> {
+        Vec::push(self, elem);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct CartesianTreeBuilder<T, S = BitVec64, K = Vec<T>> {
+    bp: S,
+    stack: K,
+    tie_break: TieBreak,
+    elems: PhantomData<T>,
+}
+
+impl<T: Ord, S: BitSink<Error = Infallible>> CartesianTreeBuilder<T, S> {
+    pub fn push(&mut self, elem: T) {
+        self.push_by(elem, T::cmp);
+    }
 }
 
-impl<T: Ord> CartesianTreeBuilder<T> {
+impl<T> CartesianTreeBuilder<T> {
     pub fn new() -> Self {
+        Self::with_storage(BitVec64::new(), Vec::new())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_storage(BitVec64::with_capacity(2 * capacity + 2), Vec::new())
+    }
+
+    pub fn build<L: Layout>(self) -> CartesianTree<BpBitVec<L>> {
+        let tie_break = self.tie_break;
+        let mut bp = self.finish_bits();
+        bp.reverse();
+        CartesianTree {
+            bp: BpBitVec::from_bitvec(bp),
+            tie_break,
+        }
+    }
+}
+
+impl<T, F: FnMut(usize, u64)> CartesianTreeBuilder<T, BackwardBits<F>> {
+    /// creates a builder for exactly `len` elements, which hands every word of
+    /// the balanced parentheses sequence to `flush` once its bits are known
+    pub(crate) fn with_backward_bits(len: usize, flush: F) -> Self {
+        Self::with_storage(BackwardBits::new(len, flush), Vec::new())
+    }
+
+    /// writes the parentheses of the super-root, the last word is flushed before returning
+    ///
+    /// returns `false` if fewer elements were pushed than given on construction,
+    /// the sequence is incomplete then
+    pub(crate) fn finish_backward(self) -> bool {
+        self.finish_bits().is_complete()
+    }
+}
+
+impl<T, S: BitSink, K: Stack<T, Error = S::Error>> CartesianTreeBuilder<T, S, K> {
+    /// creates a builder that emits the parentheses to `bp` and keeps
+    /// the right spine of the tree on `stack`
+    pub(crate) fn with_storage(bp: S, stack: K) -> Self {
         Self {
-            bp: BitVec64::new(),
-            stack: Vec::new(),
+            bp,
+            stack,
+            tie_break: TieBreak::Leftmost,
+            elems: PhantomData,
         }
     }
 
-    pub fn push(&mut self, elem: T) {
-        self.bp.push(false);
-        while matches!(self.stack.last(), Some(x) if elem.cmp(x) == std::cmp::Ordering::Less) {
+    pub fn with_tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.tie_break = tie_break;
+        self
+    }
+
+    /// like [`CartesianTreeBuilder::push_by`], but stops at the first error of the storage
+    pub(crate) fn try_push_by<F>(&mut self, elem: T, mut compare: F) -> Result<(), S::Error>
+    where
+        F: FnMut(&T, &T) -> core::cmp::Ordering,
+    {
+        self.bp.push(false)?;
+        // popping equal elements makes the new element their ancestor
+        // so the rightmost occurrence becomes the minimum
+        let pop_equal = self.tie_break == TieBreak::Rightmost;
+        while let Some(x) = self.stack.last()? {
+            match compare(&elem, x) {
+                core::cmp::Ordering::Less => {}
+                core::cmp::Ordering::Equal if pop_equal => {}
+                _ => break,
+            }
             self.stack.pop();
-            self.bp.push(true);
+            self.bp.push(true)?;
         }
-        self.stack.push(elem);
+        self.stack.push(elem)
     }
 
-    pub fn build(mut self) -> CartesianTree {
-        // super-root
-        self.bp.push(false);
-        while self.stack.pop().is_some() {
-            self.bp.push(true);
+    /// emits the parentheses of the super-root, the parent of all elements left on the stack
+    pub(crate) fn try_finish_bits(mut self) -> Result<S, S::Error> {
+        self.bp.push(false)?;
+        while self.stack.last()?.is_some() {
+            self.stack.pop();
+            self.bp.push(true)?;
         }
-        self.bp.push(true);
+        self.bp.push(true)?;
+        Ok(self.bp)
+    }
+}
 
-        self.bp.reverse();
-        CartesianTree {
-            bp: BpBitVec::from_bitvec(self.bp),
-        }
+impl<T, S: BitSink<Error = Infallible>> CartesianTreeBuilder<T, S> {
+    pub fn push_by<F>(&mut self, elem: T, compare: F)
+    where
+        F: FnMut(&T, &T) -> core::cmp::Ordering,
+    {
+        let Ok(()) = self.try_push_by(elem, compare);
+    }
+
+    fn finish_bits(self) -> S {
+        let Ok(bp) = self.try_finish_bits();
+        bp
     }
 }
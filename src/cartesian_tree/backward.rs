@@ -0,0 +1,78 @@
+use core::convert::Infallible;
+
+use crate::bp::BitVec64;
+
+/// Receives the parentheses in the order the stack based construction emits them,
+/// which is the balanced parentheses sequence from back to front
+pub(crate) trait BitSink {
+    type Error;
+
+    fn push(&mut self, bit: bool) -> Result<(), Self::Error>;
+}
+
+// the sequence is reversed once all elements are known
+impl BitSink for BitVec64 {
+    type Error = Infallible;
+
+    fn push(&mut self, bit: bool) -> Result<(), Infallible> {
+        BitVec64::push(self, bit);
+        Ok(())
+    }
+}
+
+/// Writes the parentheses of a known number of elements directly to their final
+/// positions, filling the sequence from the back
+///
+/// The construction cannot run right to left instead: the parentheses of an element
+/// count how many elements to its left it pops from the stack. Every word is handed
+/// to `flush` with its index as soon as its last bit is known, so the words arrive
+/// in decreasing order of their indices.
+pub(crate) struct BackwardBits<F> {
+    /// number of bits in front of the next one
+    remaining: usize,
+    word: u64,
+    flush: F,
+}
+
+impl<F: FnMut(usize, u64)> BackwardBits<F> {
+    /// the sequence of `len` elements has `2 * len + 2` bits
+    pub(crate) fn new(len: usize, flush: F) -> Self {
+        Self {
+            remaining: 2 * len + 2,
+            word: 0,
+            flush,
+        }
+    }
+
+    /// returns `true` if every bit of the sequence has been written
+    pub(crate) fn is_complete(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+// the builders stop after the number of elements given on construction
+impl<F: FnMut(usize, u64)> BitSink for BackwardBits<F> {
+    type Error = Infallible;
+
+    fn push(&mut self, bit: bool) -> Result<(), Infallible> {
+        self.remaining = self
+            .remaining
+            .checked_sub(1)
+            .expect("more elements than the length given on construction");
+        self.word |= (bit as u64) << (self.remaining % 64);
+        if self.remaining % 64 == 0 {
+            (self.flush)(self.remaining / 64, self.word);
+            self.word = 0;
+        }
+        Ok(())
+    }
+}
+
+impl<F> core::fmt::Debug for BackwardBits<F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BackwardBits")
+            .field("remaining", &self.remaining)
+            .field("word", &self.word)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,162 @@
+use rayon::prelude::*;
+
+use super::{CartesianTree, TieBreak};
+use crate::bp::{BitVec64, BpBitVec};
+use crate::layout::Layout;
+
+/// number of elements processed by each task
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// An element left on the stack after a chunk was processed, the stacks
+/// in front of all chunks share their common bottom parts
+struct SpineNode {
+    pos: usize,
+    below: Option<usize>,
+}
+
+impl<L: Layout> CartesianTree<BpBitVec<L>> {
+    /// builds the tree of `elems` using the rayon thread pool
+    ///
+    /// Every chunk first runs the stack based construction on its own, leaving the
+    /// right spine of its subtree on the stack. Merging the spines sequentially gives
+    /// the stack in front of every chunk, after which the chunks emit their bits in
+    /// parallel as if the elements were pushed one by one.
+    pub fn par_from_slice_by<T, F>(elems: &[T], compare: F, tie_break: TieBreak) -> Self
+    where
+        T: Sync,
+        F: Fn(&T, &T) -> core::cmp::Ordering + Sync,
+    {
+        Self::par_from_slice_in_chunks(elems, compare, tie_break, CHUNK_SIZE)
+    }
+
+    fn par_from_slice_in_chunks<T, F>(
+        elems: &[T],
+        compare: F,
+        tie_break: TieBreak,
+        chunk_size: usize,
+    ) -> Self
+    where
+        T: Sync,
+        F: Fn(&T, &T) -> core::cmp::Ordering + Sync,
+    {
+        // the same condition as `CartesianTreeBuilder::push_by` uses for popping
+        let pops = |elem: &T, x: &T| match compare(elem, x) {
+            core::cmp::Ordering::Less => true,
+            core::cmp::Ordering::Equal => tie_break == TieBreak::Rightmost,
+            core::cmp::Ordering::Greater => false,
+        };
+
+        let spines: Vec<Vec<usize>> = elems
+            .par_chunks(chunk_size)
+            .enumerate()
+            .map(|(chunk, chunk_elems)| {
+                let offset = chunk * chunk_size;
+                let mut stack: Vec<usize> = Vec::new();
+                for (pos, elem) in chunk_elems.iter().enumerate() {
+                    while stack
+                        .last()
+                        .is_some_and(|&top| pops(elem, &chunk_elems[top]))
+                    {
+                        stack.pop();
+                    }
+                    stack.push(pos);
+                }
+                stack.into_iter().map(|pos| offset + pos).collect()
+            })
+            .collect();
+
+        // the bottom of every spine is the element preferred over all others of its
+        // chunk, so it pops everything the chunk pops from the stack in front of it
+        let mut nodes: Vec<SpineNode> = Vec::new();
+        let mut heads = Vec::with_capacity(spines.len());
+        let mut head: Option<usize> = None;
+        for spine in spines {
+            heads.push(head);
+            let winner = &elems[spine[0]];
+            while let Some(top) = head.filter(|&top| pops(winner, &elems[nodes[top].pos])) {
+                head = nodes[top].below;
+            }
+            for pos in spine {
+                nodes.push(SpineNode { pos, below: head });
+                head = Some(nodes.len() - 1);
+            }
+        }
+
+        let fragments: Vec<BitVec64> = elems
+            .par_chunks(chunk_size)
+            .zip(heads)
+            .map(|(chunk_elems, mut head)| {
+                let mut bv = BitVec64::with_capacity(2 * chunk_elems.len());
+                let mut stack: Vec<&T> = Vec::new();
+                for elem in chunk_elems {
+                    bv.push(false);
+                    while stack.last().is_some_and(|top| pops(elem, top)) {
+                        stack.pop();
+                        bv.push(true);
+                    }
+                    if stack.is_empty() {
+                        while let Some(top) = head.filter(|&top| pops(elem, &elems[nodes[top].pos]))
+                        {
+                            head = nodes[top].below;
+                            bv.push(true);
+                        }
+                    }
+                    stack.push(elem);
+                }
+                bv.reverse();
+                bv
+            })
+            .collect();
+
+        // the super-root pops the remaining stack, the bits are
+        // reversed like in `CartesianTreeBuilder::build`
+        let mut bv = BitVec64::with_capacity(2 * elems.len() + 2);
+        bv.push(true);
+        while let Some(top) = head {
+            head = nodes[top].below;
+            bv.push(true);
+        }
+        bv.push(false);
+        for fragment in fragments.iter().rev() {
+            bv.extend_from_bitslice(fragment);
+        }
+
+        CartesianTree {
+            bp: BpBitVec::par_from_bitvec(bv),
+            tie_break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::CartesianTree;
+    use crate::bp::{AsBpRef, BpBitVec};
+    use crate::{layout, TieBreak};
+
+    proptest! {
+        #[test]
+        fn par_from_slice_matches_builder(
+            elems in prop::collection::vec(0u8..16, 0..20_000),
+            chunk_size in 1usize..5000,
+            rightmost in any::<bool>()
+        ) {
+            let tie_break = if rightmost { TieBreak::Rightmost } else { TieBreak::Leftmost };
+            let expected: CartesianTree<BpBitVec<layout::Fast>> =
+                CartesianTree::from_iter_by(elems.iter(), |a, b| a.cmp(b), tie_break);
+            let actual: CartesianTree<BpBitVec<layout::Fast>> =
+                CartesianTree::par_from_slice_in_chunks(&elems, u8::cmp, tie_break, chunk_size);
+
+            let (expected, actual) = (expected.bp.as_bp_ref(), actual.bp.as_bp_ref());
+            prop_assert_eq!(actual.len, expected.len);
+            prop_assert_eq!(actual.bv, expected.bv);
+            prop_assert_eq!(actual.select0_hints, expected.select0_hints);
+            prop_assert_eq!(actual.block_rank_pairs, expected.block_rank_pairs);
+            prop_assert_eq!(actual.internal_nodes, expected.internal_nodes);
+            prop_assert_eq!(actual.block_excess_min, expected.block_excess_min);
+            prop_assert_eq!(actual.superblock_excess_min, expected.superblock_excess_min);
+        }
+    }
+}
@@ -0,0 +1,453 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::range_minimum::{resolve_range, RangeMinimum};
+use crate::RmqError;
+
+/// marks a missing child
+const NIL: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Node {
+    left: usize,
+    right: usize,
+    priority: u64,
+    size: usize,
+    /// the node holding the leftmost minimum of the subtree
+    min: usize,
+    /// the position of `min` within the subtree
+    min_pos: usize,
+}
+
+/// A range minimum query index over values that change after construction
+///
+/// The values are kept in a treap ordered by position, a binary search tree
+/// balanced by random priorities. Every node stores the minimum of its subtree,
+/// so updates, insertions, deletions and queries take `O(log n)` expected time.
+/// Queries are slower than on the static indexes like [`Rmq`](crate::Rmq).
+///
+/// The priorities are seeded differently for every index, so a sequence of operations
+/// that unbalances one tree does not unbalance the next. [`DynamicRmq::with_seed`]
+/// builds the same tree for the same operations.
+///
+/// If the minimum of a range occurs more than once, the leftmost position is reported.
+///
+/// ```rust
+/// use range_minimum_query::DynamicRmq;
+///
+/// let mut rmq = DynamicRmq::from_iter([5, 3, 8, 6]);
+/// assert_eq!(rmq.range_minimum(..), Some(1));
+/// rmq.set(2, 1);
+/// assert_eq!(rmq.range_minimum(..), Some(2));
+/// rmq.insert(0, 0);
+/// assert_eq!(rmq.range_minimum(2..), Some(3));
+/// assert_eq!(rmq.remove(3), 1);
+/// rmq.push(2);
+/// assert_eq!(rmq.range_minimum(2..), Some(4));
+/// assert_eq!(rmq.pop(), Some(2));
+/// assert_eq!(rmq.get(3), Some(&6));
+/// ```
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DynamicRmq<T> {
+    nodes: Vec<Node>,
+    /// the value of every node, `None` for the slots in `free`
+    values: Vec<Option<T>>,
+    free: Vec<usize>,
+    root: usize,
+    /// state of the xorshift generator of the priorities
+    rng: u64,
+}
+
+impl<T: Ord> DynamicRmq<T> {
+    /// creates an empty index
+    pub fn new() -> Self {
+        static INSTANCES: AtomicUsize = AtomicUsize::new(0);
+        // the address of the stack differs between runs with address space layout randomization
+        let local = 0u8;
+        let address = core::ptr::addr_of!(local) as usize as u64;
+        let instance = INSTANCES.fetch_add(1, Ordering::Relaxed) as u64;
+        Self::with_seed(address.rotate_left(32) ^ instance)
+    }
+
+    /// creates an empty index whose priorities are generated from `seed`
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            nodes: Vec::new(),
+            values: Vec::new(),
+            free: Vec::new(),
+            root: NIL,
+            // the state of xorshift must not be zero
+            rng: splitmix64(seed) | 1,
+        }
+    }
+
+    /// appends `value` at the end
+    pub fn push(&mut self, value: T) {
+        self.insert(self.len(), value);
+    }
+
+    /// removes the last value and returns it, or `None` if the index is empty
+    pub fn pop(&mut self) -> Option<T> {
+        self.len().checked_sub(1).map(|last| self.remove(last))
+    }
+
+    /// replaces the value at position `i` and returns the previous value
+    ///
+    /// # Panics
+    ///
+    /// if `i` is out of bounds
+    pub fn set(&mut self, i: usize, value: T) -> T {
+        assert!(i < self.len(), "position {i} is out of bounds");
+        self.set_in(self.root, i, value)
+    }
+
+    /// inserts `value` at position `i`, shifting all values after it to the right
+    ///
+    /// # Panics
+    ///
+    /// if `i` is greater than the number of values
+    pub fn insert(&mut self, i: usize, value: T) {
+        assert!(i <= self.len(), "position {i} is out of bounds");
+        let node = self.alloc(value);
+        let (left, right) = self.split(self.root, i);
+        let left = self.merge(left, node);
+        self.root = self.merge(left, right);
+    }
+
+    /// removes and returns the value at position `i`,
+    /// shifting all values after it to the left
+    ///
+    /// # Panics
+    ///
+    /// if `i` is out of bounds
+    pub fn remove(&mut self, i: usize) -> T {
+        assert!(i < self.len(), "position {i} is out of bounds");
+        let (left, rest) = self.split(self.root, i);
+        let (node, right) = self.split(rest, 1);
+        self.root = self.merge(left, right);
+        self.free.push(node);
+        self.values[node].take().unwrap()
+    }
+
+    /// returns the position of the minimum element in `range`
+    ///
+    /// returns `None` if the range is empty or not contained in the index
+    pub fn range_minimum(&self, range: impl core::ops::RangeBounds<usize>) -> Option<usize> {
+        self.try_range_minimum(range).ok()
+    }
+
+    /// returns the position of the minimum element in `range`
+    /// or the reason why the range cannot be queried
+    pub fn try_range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        let range = resolve_range(range, self.len())?;
+        let (_, pos) = self.minimum_in(self.root, *range.start(), *range.end());
+        Ok(pos)
+    }
+
+    fn alloc(&mut self, value: T) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let node = self.free.pop().unwrap_or(self.nodes.len());
+        let leaf = Node {
+            left: NIL,
+            right: NIL,
+            priority: self.rng,
+            size: 1,
+            min: node,
+            min_pos: 0,
+        };
+        if node == self.nodes.len() {
+            self.nodes.push(leaf);
+            self.values.push(Some(value));
+        } else {
+            self.nodes[node] = leaf;
+            self.values[node] = Some(value);
+        }
+        node
+    }
+
+    /// recomputes the size and the minimum of `node` from its children
+    fn update(&mut self, node: usize) {
+        let Node { left, right, .. } = self.nodes[node];
+        let left_size = self.size(left);
+        let (mut min, mut min_pos) = (node, left_size);
+        if left != NIL && !self.less(node, self.nodes[left].min) {
+            (min, min_pos) = (self.nodes[left].min, self.nodes[left].min_pos);
+        }
+        if right != NIL && self.less(self.nodes[right].min, min) {
+            (min, min_pos) = (
+                self.nodes[right].min,
+                left_size + 1 + self.nodes[right].min_pos,
+            );
+        }
+        let size = left_size + 1 + self.size(right);
+        let node = &mut self.nodes[node];
+        (node.size, node.min, node.min_pos) = (size, min, min_pos);
+    }
+
+    /// splits the subtree of `node` into its first `k` values and the rest
+    fn split(&mut self, node: usize, k: usize) -> (usize, usize) {
+        if node == NIL {
+            return (NIL, NIL);
+        }
+        let left_size = self.size(self.nodes[node].left);
+        if k <= left_size {
+            let (left, right) = self.split(self.nodes[node].left, k);
+            self.nodes[node].left = right;
+            self.update(node);
+            (left, node)
+        } else {
+            let (left, right) = self.split(self.nodes[node].right, k - left_size - 1);
+            self.nodes[node].right = left;
+            self.update(node);
+            (node, right)
+        }
+    }
+
+    /// concatenates the subtrees `left` and `right`
+    fn merge(&mut self, left: usize, right: usize) -> usize {
+        if left == NIL {
+            return right;
+        }
+        if right == NIL {
+            return left;
+        }
+        if self.nodes[left].priority > self.nodes[right].priority {
+            self.nodes[left].right = self.merge(self.nodes[left].right, right);
+            self.update(left);
+            left
+        } else {
+            self.nodes[right].left = self.merge(left, self.nodes[right].left);
+            self.update(right);
+            right
+        }
+    }
+
+    fn set_in(&mut self, node: usize, i: usize, value: T) -> T {
+        let Node { left, right, .. } = self.nodes[node];
+        let left_size = self.size(left);
+        let old = match i.cmp(&left_size) {
+            core::cmp::Ordering::Less => self.set_in(left, i, value),
+            core::cmp::Ordering::Equal => self.values[node].replace(value).unwrap(),
+            core::cmp::Ordering::Greater => self.set_in(right, i - left_size - 1, value),
+        };
+        self.update(node);
+        old
+    }
+
+    /// returns the node and the position of the minimum of the positions
+    /// `start..=end` within the subtree of `node`
+    fn minimum_in(&self, node: usize, start: usize, end: usize) -> (usize, usize) {
+        let Node {
+            left,
+            right,
+            size,
+            min,
+            min_pos,
+            ..
+        } = self.nodes[node];
+        if start == 0 && end == size - 1 {
+            return (min, min_pos);
+        }
+        let left_size = self.size(left);
+        let mut best: Option<(usize, usize)> = None;
+        let mut consider = |candidate: (usize, usize)| {
+            if best.is_none_or(|best| self.less(candidate.0, best.0)) {
+                best = Some(candidate);
+            }
+        };
+        if start < left_size {
+            consider(self.minimum_in(left, start, end.min(left_size - 1)));
+        }
+        if (start..=end).contains(&left_size) {
+            consider((node, left_size));
+        }
+        if end > left_size {
+            let offset = left_size + 1;
+            let (min, pos) = self.minimum_in(right, start.max(offset) - offset, end - offset);
+            consider((min, pos + offset));
+        }
+        best.unwrap()
+    }
+
+    /// returns `true` if the value of node `a` is smaller than the value of node `b`
+    fn less(&self, a: usize, b: usize) -> bool {
+        self.values[a] < self.values[b]
+    }
+}
+
+impl<T> DynamicRmq<T> {
+    /// returns the value at position `i`, or `None` if it is out of bounds
+    pub fn get(&self, i: usize) -> Option<&T> {
+        let mut node = self.root;
+        let mut i = i;
+        while node != NIL {
+            let left_size = self.size(self.nodes[node].left);
+            match i.cmp(&left_size) {
+                core::cmp::Ordering::Less => node = self.nodes[node].left,
+                core::cmp::Ordering::Equal => return self.values[node].as_ref(),
+                core::cmp::Ordering::Greater => {
+                    i -= left_size + 1;
+                    node = self.nodes[node].right;
+                }
+            }
+        }
+        None
+    }
+
+    /// returns the number of values
+    pub fn len(&self) -> usize {
+        self.size(self.root)
+    }
+
+    /// returns `true` if the index does not contain any values
+    pub fn is_empty(&self) -> bool {
+        self.root == NIL
+    }
+
+    fn size(&self, node: usize) -> usize {
+        if node == NIL {
+            0
+        } else {
+            self.nodes[node].size
+        }
+    }
+}
+
+/// spreads the bits of `x` over the whole word, so seeds differing in few bits
+/// start from unrelated states
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl<T: Ord> Default for DynamicRmq<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> Extend<T> for DynamicRmq<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T: Ord> FromIterator<T> for DynamicRmq<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut rmq = Self::new();
+        rmq.extend(iter);
+        rmq
+    }
+}
+
+impl<T: Ord> RangeMinimum for DynamicRmq<T> {
+    fn len(&self) -> usize {
+        DynamicRmq::len(self)
+    }
+
+    fn try_range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        DynamicRmq::try_range_minimum(self, range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::DynamicRmq;
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Push(u8),
+        Pop,
+        Set(prop::sample::Index, u8),
+        Insert(prop::sample::Index, u8),
+        Remove(prop::sample::Index),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (0u8..16).prop_map(Op::Push),
+            Just(Op::Pop),
+            (any::<prop::sample::Index>(), 0u8..16).prop_map(|(i, v)| Op::Set(i, v)),
+            (any::<prop::sample::Index>(), 0u8..16).prop_map(|(i, v)| Op::Insert(i, v)),
+            any::<prop::sample::Index>().prop_map(Op::Remove),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn operations_match_vec(
+            ops in prop::collection::vec(op(), 1..300),
+            queries in prop::collection::vec((any::<prop::sample::Index>(), any::<prop::sample::Index>()), 1..20),
+            seed in any::<u64>()
+        ) {
+            let mut rmq = DynamicRmq::with_seed(seed);
+            let mut elems: Vec<u8> = Vec::new();
+            for op in ops {
+                match op {
+                    Op::Push(v) => {
+                        rmq.push(v);
+                        elems.push(v);
+                    }
+                    Op::Pop => prop_assert_eq!(rmq.pop(), elems.pop()),
+                    Op::Set(i, v) if !elems.is_empty() => {
+                        let i = i.index(elems.len());
+                        prop_assert_eq!(rmq.set(i, v), core::mem::replace(&mut elems[i], v));
+                    }
+                    Op::Insert(i, v) => {
+                        let i = i.index(elems.len() + 1);
+                        rmq.insert(i, v);
+                        elems.insert(i, v);
+                    }
+                    Op::Remove(i) if !elems.is_empty() => {
+                        let i = i.index(elems.len());
+                        prop_assert_eq!(rmq.remove(i), elems.remove(i));
+                    }
+                    _ => {}
+                }
+                prop_assert_eq!(rmq.len(), elems.len());
+            }
+
+            for (i, elem) in elems.iter().enumerate() {
+                prop_assert_eq!(rmq.get(i), Some(elem));
+            }
+            prop_assert_eq!(rmq.get(elems.len()), None);
+            if elems.is_empty() {
+                prop_assert_eq!(rmq.range_minimum(..), None);
+                return Ok(());
+            }
+            for (start, end) in queries {
+                let (start, end) = (start.index(elems.len()), end.index(elems.len()));
+                let range = start.min(end)..=start.max(end);
+                let expected = range.clone().min_by_key(|&i| (elems[i], i));
+                prop_assert_eq!(rmq.range_minimum(range), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn instances_are_seeded_differently() {
+        let a = DynamicRmq::<u8>::new();
+        let b = DynamicRmq::<u8>::new();
+        assert_ne!(a.rng, b.rng);
+        assert_eq!(
+            DynamicRmq::<u8>::with_seed(7).rng,
+            DynamicRmq::<u8>::with_seed(7).rng
+        );
+    }
+}
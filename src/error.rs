@@ -0,0 +1,82 @@
+use crate::TieBreak;
+
+/// Errors reported by the fallible range minimum queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RmqError {
+    /// the queried range does not contain any position
+    EmptyRange,
+    /// the queried range ends at or beyond the number of indexed elements
+    OutOfBounds {
+        /// the last position covered by the queried range
+        end: usize,
+        /// the number of indexed elements
+        len: usize,
+    },
+    /// the index does not contain any elements
+    EmptyIndex,
+    /// the query needs an index built with the other [`TieBreak`], its
+    /// Cartesian tree cannot tell equal elements apart from larger ones
+    WrongTieBreak {
+        /// the tie break the query requires
+        required: TieBreak,
+    },
+}
+
+impl core::fmt::Display for RmqError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RmqError::EmptyRange => write!(f, "range is empty"),
+            RmqError::OutOfBounds { end, len } => {
+                write!(f, "range end {end} is out of bounds for length {len}")
+            }
+            RmqError::EmptyIndex => write!(f, "index is empty"),
+            RmqError::WrongTieBreak { required } => {
+                write!(
+                    f,
+                    "query requires an index built with TieBreak::{required:?}"
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for RmqError {}
+
+/// Errors reported when loading a serialized index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatError {
+    /// the data does not start with the expected magic bytes
+    InvalidMagic,
+    /// the data was written in a format version this crate cannot read
+    UnsupportedVersion(u32),
+    /// the data is shorter than announced by its header
+    Truncated,
+    /// the data is not aligned to 8 bytes and cannot be borrowed in place
+    Misaligned,
+    /// the stored components are inconsistent in size or contents
+    InvalidLayout,
+    /// the data cannot be borrowed in place on big-endian targets
+    UnsupportedEndianness,
+    /// the data was written in another [`Layout`](crate::layout::Layout) than requested
+    LayoutMismatch,
+}
+
+impl core::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FormatError::InvalidMagic => write!(f, "invalid magic bytes"),
+            FormatError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}")
+            }
+            FormatError::Truncated => write!(f, "data is truncated"),
+            FormatError::Misaligned => write!(f, "data is not aligned to 8 bytes"),
+            FormatError::InvalidLayout => write!(f, "components are inconsistent"),
+            FormatError::UnsupportedEndianness => {
+                write!(f, "zero-copy loading requires a little-endian target")
+            }
+            FormatError::LayoutMismatch => write!(f, "data was written in another layout"),
+        }
+    }
+}
+
+impl core::error::Error for FormatError {}
@@ -0,0 +1,535 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::vec::Vec;
+
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cartesian_tree::{BitSink, CartesianTreeBuilder, Stack};
+use crate::{format, TieBreak};
+
+/// memory used by [`ExternalRmqBuilder::new`]
+const DEFAULT_MEMORY_BUDGET: usize = 64 << 20;
+
+/// size of the buffers that convert between values and bytes in the temporary files
+const IO_BUFFER_BYTES: usize = 4096;
+
+/// Values that are stored in a fixed number of little-endian bytes
+/// by [`ExternalRmqBuilder`]
+pub trait ExternalValue: Ord + Sized {
+    /// number of bytes of every value
+    const SIZE: usize;
+
+    /// writes the value to the first `SIZE` bytes of `bytes`
+    fn write_le(&self, bytes: &mut [u8]);
+
+    /// reads a value from the first `SIZE` bytes of `bytes`
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_external_value {
+    ($($t:ty),*) => {
+        $(
+            impl ExternalValue for $t {
+                const SIZE: usize = core::mem::size_of::<$t>();
+
+                fn write_le(&self, bytes: &mut [u8]) {
+                    bytes[..Self::SIZE].copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_le(bytes: &[u8]) -> Self {
+                    <$t>::from_le_bytes(bytes[..Self::SIZE].try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_external_value!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+/// Builds an index over more values than fit in memory
+///
+/// The values are read once. The right spine of the Cartesian tree and the
+/// balanced parentheses sequence are kept in memory up to the memory budget,
+/// beyond it the bottom of the spine and the finished words of the sequence
+/// are moved to temporary files. The index is written in the format of
+/// [`Rmq::to_compact_bytes`](crate::Rmq::to_compact_bytes), about `2n` bits
+/// for `n` values, and loaded with [`Rmq::from_compact_bytes`](crate::Rmq::from_compact_bytes).
+///
+/// ```rust
+/// use range_minimum_query::{ExternalRmqBuilder, Rmq};
+///
+/// let values: Vec<u32> = (0..100_000).map(|i| (i * 7919) % 100_003).collect();
+/// let mut bytes = Vec::new();
+/// ExternalRmqBuilder::new()
+///     .with_memory_budget(4096)
+///     .write_from_iter(values.iter().copied(), &mut bytes)
+///     .unwrap();
+///
+/// let rmq = Rmq::from_compact_bytes(&bytes).unwrap();
+/// assert_eq!(rmq.range_minimum(..), Some(0));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ExternalRmqBuilder<T> {
+    memory_budget: usize,
+    temp_dir: PathBuf,
+    tie_break: TieBreak,
+    values: PhantomData<fn(T)>,
+}
+
+impl<T: ExternalValue> ExternalRmqBuilder<T> {
+    /// creates a builder using 64 MiB of memory and the temporary directory of the system
+    pub fn new() -> Self {
+        Self {
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            temp_dir: std::env::temp_dir(),
+            tie_break: TieBreak::Leftmost,
+            values: PhantomData,
+        }
+    }
+
+    /// sets the number of bytes of values and words kept in memory,
+    /// half of them are used by the stack and half by the sequence
+    pub fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// sets the directory of the temporary files
+    pub fn with_temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = dir.into();
+        self
+    }
+
+    /// selects which position of a repeated minimum is reported by the queries
+    pub fn with_tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.tie_break = tie_break;
+        self
+    }
+
+    /// writes the index over the values of `iter` to `writer`
+    pub fn write_from_iter<I, W>(&self, iter: I, writer: W) -> io::Result<()>
+    where
+        I: IntoIterator<Item = T>,
+        W: Write,
+    {
+        self.write_from_results(iter.into_iter().map(Ok), writer)
+    }
+
+    /// writes the index over the values read from `reader` to `writer`,
+    /// every value is stored in [`ExternalValue::SIZE`] little-endian bytes
+    ///
+    /// returns an error of kind [`io::ErrorKind::UnexpectedEof`] if the
+    /// input ends within a value
+    pub fn write_from_reader<R, W>(&self, reader: R, writer: W) -> io::Result<()>
+    where
+        R: Read,
+        W: Write,
+    {
+        let mut reader = io::BufReader::new(reader);
+        let mut bytes = std::vec![0; T::SIZE];
+        let values = core::iter::from_fn(move || match read_value(&mut reader, &mut bytes) {
+            Ok(true) => Some(Ok(T::read_le(&bytes))),
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        });
+        self.write_from_results(values, writer)
+    }
+
+    fn write_from_results<I, W>(&self, values: I, writer: W) -> io::Result<()>
+    where
+        I: Iterator<Item = io::Result<T>>,
+        W: Write,
+    {
+        let half_budget = self.memory_budget / 2;
+        let mut builder = CartesianTreeBuilder::with_storage(
+            SpillBits::new(half_budget / 8, &self.temp_dir),
+            SpillStack::new(half_budget / T::SIZE, &self.temp_dir),
+        )
+        .with_tie_break(self.tie_break);
+        for elem in values {
+            builder.try_push_by(elem?, T::cmp)?;
+        }
+        // the stack is dropped before the sequence is written
+        let bits = builder.try_finish_bits()?;
+        bits.write_compact(writer, self.tie_break)
+    }
+}
+
+impl<T: ExternalValue> Default for ExternalRmqBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// reads the next value into `bytes`, returns `false` at the end of the input
+fn read_value(reader: &mut impl Read, bytes: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < bytes.len() {
+        match reader.read(&mut bytes[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(true)
+}
+
+/// the id of the next temporary file of this process
+static TEMP_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A file in the temporary directory that is removed when dropped
+#[derive(Debug)]
+struct TempFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// creates a file with a name unique to this process, names left behind
+    /// by other processes with the same id are skipped
+    fn create(dir: &Path) -> io::Result<Self> {
+        loop {
+            let id = TEMP_FILE_ID.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(std::format!("rmq-{}-{}.tmp", std::process::id(), id));
+            match OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => return Ok(Self { file, path }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// pushes `value` without growing the allocation of `vec` beyond `capacity` elements,
+/// `vec` has to hold fewer than `capacity` elements
+fn push_bounded<T>(vec: &mut Vec<T>, capacity: usize, value: T) {
+    if vec.len() == vec.capacity() {
+        vec.reserve_exact(vec.len().max(4).min(capacity - vec.len()));
+    }
+    vec.push(value);
+}
+
+/// The stack of the construction, whose bottom is moved to a file
+/// once more than `capacity` values are in memory
+#[derive(Debug)]
+struct SpillStack<'a, T> {
+    top: Vec<T>,
+    capacity: usize,
+    /// number of values in the file, the bottom of the stack
+    spilled: u64,
+    file: Option<TempFile>,
+    temp_dir: &'a Path,
+    /// the bytes of a few values on their way to or from the file
+    buffer: Vec<u8>,
+}
+
+impl<'a, T: ExternalValue> SpillStack<'a, T> {
+    fn new(capacity: usize, temp_dir: &'a Path) -> Self {
+        Self {
+            top: Vec::new(),
+            // half of the values are moved at a time
+            capacity: capacity.max(2),
+            spilled: 0,
+            file: None,
+            temp_dir,
+            buffer: std::vec![0; IO_BUFFER_BYTES.max(T::SIZE) / T::SIZE * T::SIZE],
+        }
+    }
+}
+
+impl<T: ExternalValue> Stack<T> for SpillStack<'_, T> {
+    type Error = io::Error;
+
+    /// returns the top of the stack, reading values back from the file if necessary
+    fn last(&mut self) -> io::Result<Option<&T>> {
+        if self.top.is_empty() && self.spilled > 0 {
+            let count = self.spilled.min((self.capacity / 2) as u64);
+            self.spilled -= count;
+            let file = &mut self.file.as_mut().unwrap().file;
+            file.seek(SeekFrom::Start(self.spilled * T::SIZE as u64))?;
+            let mut remaining = count as usize * T::SIZE;
+            while remaining > 0 {
+                let n_bytes = remaining.min(self.buffer.len());
+                let bytes = &mut self.buffer[..n_bytes];
+                file.read_exact(bytes)?;
+                for value in bytes.chunks_exact(T::SIZE) {
+                    push_bounded(&mut self.top, self.capacity, T::read_le(value));
+                }
+                remaining -= bytes.len();
+            }
+        }
+        Ok(self.top.as_slice().last())
+    }
+
+    fn pop(&mut self) {
+        self.top.pop();
+    }
+
+    fn push(&mut self, elem: T) -> io::Result<()> {
+        if self.top.len() == self.capacity {
+            let count = self.capacity / 2;
+            let file = match &mut self.file {
+                Some(file) => file,
+                None => self.file.insert(TempFile::create(self.temp_dir)?),
+            };
+            file.file
+                .seek(SeekFrom::Start(self.spilled * T::SIZE as u64))?;
+            for values in self.top[..count].chunks(self.buffer.len() / T::SIZE) {
+                let bytes = &mut self.buffer[..values.len() * T::SIZE];
+                for (value, bytes) in values.iter().zip(bytes.chunks_exact_mut(T::SIZE)) {
+                    value.write_le(bytes);
+                }
+                file.file.write_all(bytes)?;
+            }
+            self.top.drain(..count);
+            self.spilled += count as u64;
+        }
+        push_bounded(&mut self.top, self.capacity, elem);
+        Ok(())
+    }
+}
+
+/// The parentheses in the order of the construction, whose finished words
+/// are appended to a file once more than `capacity` words are in memory
+#[derive(Debug)]
+struct SpillBits<'a> {
+    words: Vec<u64>,
+    capacity: usize,
+    /// number of bits pushed so far
+    len: u64,
+    /// number of words in the file, the front of the sequence
+    spilled: u64,
+    file: Option<TempFile>,
+    temp_dir: &'a Path,
+}
+
+impl<'a> SpillBits<'a> {
+    fn new(capacity: usize, temp_dir: &'a Path) -> Self {
+        Self {
+            words: Vec::new(),
+            capacity: capacity.max(1),
+            len: 0,
+            spilled: 0,
+            file: None,
+            temp_dir,
+        }
+    }
+
+    /// writes the reversed sequence in the compact format, reading
+    /// the words of the file from back to front into the buffer of the words
+    fn write_compact(self, writer: impl Write, tie_break: TieBreak) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(writer);
+        format::write_compact_header(&mut writer, tie_break, self.len)?;
+
+        let mut output = ReversedWords {
+            writer: &mut writer,
+            shift: (self.len % 64) as u32,
+            pending: None,
+        };
+        for &word in self.words.iter().rev() {
+            output.push(word)?;
+        }
+        if let Some(mut file) = self.file {
+            let mut words = self.words;
+            let mut bytes = [0; IO_BUFFER_BYTES];
+            let mut remaining = self.spilled;
+            while remaining > 0 {
+                let count = remaining.min(self.capacity as u64);
+                remaining -= count;
+                file.file.seek(SeekFrom::Start(remaining * 8))?;
+                words.clear();
+                let mut left = count as usize * 8;
+                while left > 0 {
+                    let bytes = &mut bytes[..left.min(IO_BUFFER_BYTES)];
+                    file.file.read_exact(bytes)?;
+                    words.extend(
+                        bytes
+                            .chunks_exact(8)
+                            .map(|word| u64::from_le_bytes(word.try_into().unwrap())),
+                    );
+                    left -= bytes.len();
+                }
+                for &word in words.iter().rev() {
+                    output.push(word)?;
+                }
+            }
+        }
+        output.finish()?;
+        writer.flush()
+    }
+}
+
+impl BitSink for SpillBits<'_> {
+    type Error = io::Error;
+
+    fn push(&mut self, bit: bool) -> io::Result<()> {
+        if self.len % 64 == 0 {
+            if self.words.len() == self.capacity {
+                let file = match &mut self.file {
+                    Some(file) => file,
+                    None => self.file.insert(TempFile::create(self.temp_dir)?),
+                };
+                let mut bytes = [0; IO_BUFFER_BYTES];
+                for words in self.words.chunks(bytes.len() / 8) {
+                    for (word, bytes) in words.iter().zip(bytes.chunks_exact_mut(8)) {
+                        bytes.copy_from_slice(&word.to_le_bytes());
+                    }
+                    file.file.write_all(&bytes[..words.len() * 8])?;
+                }
+                self.words.clear();
+                self.spilled += self.capacity as u64;
+            }
+            push_bounded(&mut self.words, self.capacity, 0);
+        }
+        *self.words.last_mut().unwrap() |= (bit as u64) << (self.len % 64);
+        self.len += 1;
+        Ok(())
+    }
+}
+
+/// Reverses the bits of the words pushed from the back of a sequence
+/// and writes them as the words of the reversed sequence
+struct ReversedWords<W> {
+    writer: W,
+    /// number of bits of the partial last word of the sequence, 0 if it is full
+    shift: u32,
+    /// the bits of the reversed sequence that do not fill a word yet
+    pending: Option<u64>,
+}
+
+impl<W: Write> ReversedWords<W> {
+    fn push(&mut self, word: u64) -> io::Result<()> {
+        let reversed = word.reverse_bits();
+        if self.shift == 0 {
+            return self.writer.write_all(&reversed.to_le_bytes());
+        }
+        // the partial last word is pushed first and moves all others by its bits
+        if let Some(pending) = self.pending {
+            self.writer
+                .write_all(&(pending | reversed << self.shift).to_le_bytes())?;
+        }
+        self.pending = Some(reversed >> (64 - self.shift));
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        match self.pending {
+            Some(pending) => self.writer.write_all(&pending.to_le_bytes()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::ExternalRmqBuilder;
+    use crate::{Rmq, TieBreak};
+
+    /// an empty directory for the temporary files of one test
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("rmq-test-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn temp_files_skip_names_in_use() {
+        use core::sync::atomic::Ordering;
+
+        let dir = temp_dir("clash");
+        let next = super::TEMP_FILE_ID.load(Ordering::Relaxed);
+        let stale: Vec<_> = (next..next + 16)
+            .map(|id| dir.join(format!("rmq-{}-{id}.tmp", std::process::id())))
+            .collect();
+        for path in &stale {
+            std::fs::write(path, b"").unwrap();
+        }
+        let file = super::TempFile::create(&dir).unwrap();
+        assert!(!stale.contains(&file.path));
+        for path in &stale {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn spilled_construction_matches_from_iter(
+            elems in prop::collection::vec(0u16..64, 0..5000),
+            sorted in any::<bool>(),
+            rightmost in any::<bool>(),
+            memory_budget in 0usize..200
+        ) {
+            let mut elems = elems;
+            // increasing values are never popped and spill the stack
+            if sorted {
+                elems.sort_unstable();
+            }
+            let tie_break = if rightmost { TieBreak::Rightmost } else { TieBreak::Leftmost };
+            let dir = temp_dir("spilled");
+            let mut bytes = Vec::new();
+            ExternalRmqBuilder::new()
+                .with_memory_budget(memory_budget)
+                .with_temp_dir(&dir)
+                .with_tie_break(tie_break)
+                .write_from_iter(elems.iter().copied(), &mut bytes)
+                .unwrap();
+
+            let rmq = Rmq::from_iter_with_tie_break(elems.iter(), tie_break);
+            prop_assert_eq!(bytes, rmq.to_compact_bytes());
+            prop_assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+            std::fs::remove_dir(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn spills_larger_than_io_buffer() {
+        // equal values are not popped, so the stack holds all of them
+        let values: Vec<u8> = (0..200_000u32).map(|i| (i / 1000) as u8).collect();
+        let dir = temp_dir("large");
+        let mut bytes = Vec::new();
+        ExternalRmqBuilder::new()
+            .with_memory_budget(32 << 10)
+            .with_temp_dir(&dir)
+            .write_from_iter(values.iter().copied(), &mut bytes)
+            .unwrap();
+        std::fs::remove_dir(&dir).unwrap();
+        assert_eq!(bytes, Rmq::from_iter(&values).to_compact_bytes());
+    }
+
+    #[test]
+    fn values_are_read_little_endian() {
+        let values: Vec<i32> = (0..10_000).map(|i| (i * 7919) % 10_007 - 5000).collect();
+        let input: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let dir = temp_dir("reader");
+        let mut bytes = Vec::new();
+        ExternalRmqBuilder::<i32>::new()
+            .with_memory_budget(1024)
+            .with_temp_dir(&dir)
+            .write_from_reader(&input[..], &mut bytes)
+            .unwrap();
+        std::fs::remove_dir(&dir).unwrap();
+        assert_eq!(bytes, Rmq::from_iter(&values).to_compact_bytes());
+
+        let err = ExternalRmqBuilder::<i32>::new()
+            .write_from_reader(&input[..input.len() - 1], &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,728 @@
+//! Versioned binary layout of the succinct index
+//!
+//! All values are stored little-endian. The layout starts with a 72 byte header
+//!
+//! | offset | type      | content                                   |
+//! |--------|-----------|-------------------------------------------|
+//! | 0      | `[u8; 8]` | magic bytes `RMQ-BP\0\0`                  |
+//! | 8      | `u32`     | format version                            |
+//! | 12     | `u32`     | flags, bit 0 set for [`TieBreak::Rightmost`], bits 8 to 15 the [`Layout`] |
+//! | 16     | `u64`     | length of the bit vector in bits          |
+//! | 24     | `u64`     | number of internal nodes of the min-tree  |
+//! | 32     | `u64`     | number of words of the bit vector         |
+//! | 40     | `u64`     | number of `select0` hints                 |
+//! | 48     | `u64`     | number of block rank pairs                |
+//! | 56     | `u64`     | number of superblock excess minima        |
+//! | 64     | `u64`     | number of block excess minima             |
+//!
+//! followed by the sections in the same order, each one padded to a multiple of 8 bytes:
+//! the bit vector words (`u64`), the `select0` hints (`u64`), the block rank
+//! pairs (`u64`), the superblock excess minima (`i64`) and the block excess minima (`i16`).
+//! Every section starts 8-byte aligned, so an aligned buffer can be queried in place.
+//!
+//! The compact layout only stores the balanced parentheses sequence after a 24 byte
+//! header of the magic bytes `RMQ-BPC\0`, the format version, the flags and
+//! the length of the bit vector in bits. The rank, select and excess structures
+//! are rebuilt when it is loaded, so the flags do not store a layout.
+//!
+//! [`Layout`]: crate::layout::Layout
+
+use alloc::vec::Vec;
+
+use core::marker::PhantomData;
+
+use crate::bp::{AsBpRef, BitVec64, BpBitVec, BpRef};
+use crate::cartesian_tree::CartesianTree;
+use crate::layout::{self, Layout};
+use crate::{FormatError, Rmq, RmqError, TieBreak};
+
+const MAGIC: [u8; 8] = *b"RMQ-BP\0\0";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 72;
+const ALIGNMENT: usize = 8;
+const COMPACT_MAGIC: [u8; 8] = *b"RMQ-BPC\0";
+const COMPACT_HEADER_SIZE: usize = 24;
+const FLAG_RIGHTMOST: u32 = 1;
+const LAYOUT_SHIFT: u32 = 8;
+const LAYOUT_MASK: u32 = 0xff << LAYOUT_SHIFT;
+
+fn write_flags(tie_break: TieBreak, layout_id: u32) -> u32 {
+    let tie_break = match tie_break {
+        TieBreak::Leftmost => 0,
+        TieBreak::Rightmost => FLAG_RIGHTMOST,
+    };
+    tie_break | layout_id << LAYOUT_SHIFT
+}
+
+/// returns the tie break and the id of the layout stored in the flags
+fn read_flags(bytes: &[u8]) -> Result<(TieBreak, u32), FormatError> {
+    let flags = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+    if flags & !(FLAG_RIGHTMOST | LAYOUT_MASK) != 0 {
+        return Err(FormatError::InvalidLayout);
+    }
+    let tie_break = if flags & FLAG_RIGHTMOST == 0 {
+        TieBreak::Leftmost
+    } else {
+        TieBreak::Rightmost
+    };
+    Ok((tie_break, (flags & LAYOUT_MASK) >> LAYOUT_SHIFT))
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn write_bp<W: std::io::Write, B: AsBpRef>(
+    tree: &CartesianTree<B>,
+    mut writer: W,
+) -> std::io::Result<()> {
+    let bp = tree.bp.as_bp_ref();
+    let flags = write_flags(tree.tie_break, <B::Layout as layout::sealed::Sealed>::ID);
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&flags.to_le_bytes())?;
+    for value in [
+        bp.len as u64,
+        bp.internal_nodes,
+        bp.bv.len() as u64,
+        bp.select0_hints.len() as u64,
+        bp.block_rank_pairs.len() as u64,
+        bp.superblock_excess_min.len() as u64,
+        bp.block_excess_min.len() as u64,
+    ] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    for &word in bp
+        .bv
+        .iter()
+        .chain(bp.select0_hints)
+        .chain(bp.block_rank_pairs)
+    {
+        writer.write_all(&word.to_le_bytes())?;
+    }
+    for &min in bp.superblock_excess_min {
+        writer.write_all(&min.to_le_bytes())?;
+    }
+    for &min in bp.block_excess_min {
+        writer.write_all(&min.to_le_bytes())?;
+    }
+    let padding = (ALIGNMENT - bp.block_excess_min.len() * 2 % ALIGNMENT) % ALIGNMENT;
+    writer.write_all(&[0; ALIGNMENT][..padding])
+}
+
+pub(crate) fn read_bp<L: Layout>(bytes: &[u8]) -> Result<CartesianTree<BpRef<'_, L>>, FormatError> {
+    if cfg!(target_endian = "big") {
+        return Err(FormatError::UnsupportedEndianness);
+    }
+    if bytes.len() < HEADER_SIZE {
+        return Err(FormatError::Truncated);
+    }
+    if bytes[..8] != MAGIC {
+        return Err(FormatError::InvalidMagic);
+    }
+    let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    if version != VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+    let (tie_break, layout_id) = read_flags(bytes)?;
+    if layout_id != L::ID {
+        return Err(FormatError::LayoutMismatch);
+    }
+    if bytes.as_ptr().align_offset(ALIGNMENT) != 0 {
+        return Err(FormatError::Misaligned);
+    }
+    let header_field =
+        |i: usize| u64::from_le_bytes(bytes[16 + 8 * i..24 + 8 * i].try_into().unwrap());
+
+    let mut reader = SectionReader {
+        bytes,
+        pos: HEADER_SIZE,
+    };
+    let len = usize::try_from(header_field(0)).map_err(|_| FormatError::InvalidLayout)?;
+    let bp = BpRef {
+        len,
+        internal_nodes: header_field(1),
+        bv: reader.section(header_field(2))?,
+        select0_hints: reader.section(header_field(3))?,
+        block_rank_pairs: reader.section(header_field(4))?,
+        superblock_excess_min: reader.section(header_field(5))?,
+        block_excess_min: reader.section(header_field(6))?,
+        layout: PhantomData,
+    };
+    if !bp.has_valid_layout() || !bp.has_valid_contents() {
+        return Err(FormatError::InvalidLayout);
+    }
+    Ok(CartesianTree { bp, tie_break })
+}
+
+pub(crate) fn write_compact_bp(tree: &CartesianTree<impl AsBpRef>) -> Vec<u8> {
+    let bp = tree.bp.as_bp_ref();
+    let mut bytes = Vec::with_capacity(COMPACT_HEADER_SIZE + bp.bv.len() * 8);
+    bytes.extend_from_slice(&COMPACT_MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&write_flags(tree.tie_break, 0).to_le_bytes());
+    bytes.extend_from_slice(&(bp.len as u64).to_le_bytes());
+    for word in bp.bv {
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    bytes
+}
+
+/// writes the header of the compact layout for a sequence of `len` bits,
+/// the words of the sequence have to follow
+#[cfg(feature = "std")]
+pub(crate) fn write_compact_header<W: std::io::Write>(
+    mut writer: W,
+    tie_break: TieBreak,
+    len: u64,
+) -> std::io::Result<()> {
+    writer.write_all(&COMPACT_MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&write_flags(tie_break, 0).to_le_bytes())?;
+    writer.write_all(&len.to_le_bytes())
+}
+
+/// number of words of the balanced parentheses sequence
+/// buffered by [`write_compact_from_iter`]
+#[cfg(feature = "std")]
+const STREAM_BUFFER_WORDS: usize = 1 << 13;
+
+#[cfg(feature = "std")]
+pub(crate) fn write_compact_from_iter<T, I, F, W>(
+    iter: I,
+    compare: F,
+    tie_break: TieBreak,
+    writer: W,
+) -> std::io::Result<()>
+where
+    I: IntoIterator<Item = T>,
+    I::IntoIter: ExactSizeIterator,
+    F: FnMut(&T, &T) -> core::cmp::Ordering,
+    W: std::io::Write + std::io::Seek,
+{
+    write_compact_buffered(iter, compare, tie_break, writer, STREAM_BUFFER_WORDS)
+}
+
+#[cfg(feature = "std")]
+fn write_compact_buffered<T, I, F, W>(
+    iter: I,
+    mut compare: F,
+    tie_break: TieBreak,
+    mut writer: W,
+    buffer_words: usize,
+) -> std::io::Result<()>
+where
+    I: IntoIterator<Item = T>,
+    I::IntoIter: ExactSizeIterator,
+    F: FnMut(&T, &T) -> core::cmp::Ordering,
+    W: std::io::Write + std::io::Seek,
+{
+    use std::cell::RefCell;
+    use std::io::SeekFrom;
+
+    let mut iter = iter.into_iter();
+    let n_elems = iter.len();
+    let len = 2 * n_elems as u64 + 2;
+    let start = writer.stream_position()?;
+    write_compact_header(&mut writer, tie_break, len)?;
+    let words_start = start + COMPACT_HEADER_SIZE as u64;
+
+    // the words arrive in decreasing order, every full buffer is written in one go
+    let mut buffer: Vec<u64> = Vec::with_capacity(buffer_words);
+    // shared with the loop below, which stops reading values after an error
+    let result = RefCell::new(Ok(()));
+    let flush = |i: usize, word: u64| {
+        buffer.push(word);
+        if result.borrow().is_ok() && (buffer.len() == buffer_words || i == 0) {
+            let bytes: Vec<u8> = buffer.iter().rev().flat_map(|w| w.to_le_bytes()).collect();
+            *result.borrow_mut() = writer
+                .seek(SeekFrom::Start(words_start + 8 * i as u64))
+                .and_then(|_| writer.write_all(&bytes));
+        }
+        if buffer.len() == buffer_words {
+            buffer.clear();
+        }
+    };
+    let mut builder =
+        crate::cartesian_tree::CartesianTreeBuilder::with_backward_bits(n_elems, flush)
+            .with_tie_break(tie_break);
+    for item in iter.by_ref().take(n_elems) {
+        builder.push_by(item, &mut compare);
+        if result.borrow().is_err() {
+            break;
+        }
+    }
+    let complete = builder.finish_backward();
+    result.into_inner()?;
+    if !complete || iter.next().is_some() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "the iterator yielded a different number of elements than its length",
+        ));
+    }
+    writer.seek(SeekFrom::Start(words_start + 8 * len.div_ceil(64)))?;
+    Ok(())
+}
+
+pub(crate) fn read_compact_bp(bytes: &[u8]) -> Result<CartesianTree, FormatError> {
+    if bytes.len() < COMPACT_HEADER_SIZE {
+        return Err(FormatError::Truncated);
+    }
+    if bytes[..8] != COMPACT_MAGIC {
+        return Err(FormatError::InvalidMagic);
+    }
+    let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    if version != VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+    let (tie_break, layout_id) = read_flags(bytes)?;
+    if layout_id != 0 {
+        return Err(FormatError::InvalidLayout);
+    }
+    let len = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
+    let len = usize::try_from(len).map_err(|_| FormatError::InvalidLayout)?;
+    if len < 2 || len % 2 != 0 {
+        return Err(FormatError::InvalidLayout);
+    }
+    let words = &bytes[COMPACT_HEADER_SIZE..];
+    if words.len() / 8 < len.div_ceil(64) {
+        return Err(FormatError::Truncated);
+    }
+    let mut words: Vec<u64> = words
+        .chunks_exact(8)
+        .take(len.div_ceil(64))
+        .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+        .collect();
+    // the rank structures count whole words, so clear the bits past the end
+    if len % 64 != 0 {
+        *words.last_mut().unwrap() &= (1 << (len % 64)) - 1;
+    }
+    let mut bv = BitVec64::from_vec(words);
+    bv.truncate(len);
+    if !is_enclosed(&bv) {
+        return Err(FormatError::InvalidLayout);
+    }
+    Ok(CartesianTree {
+        bp: BpBitVec::from_bitvec(bv),
+        tie_break,
+    })
+}
+
+/// checks that `bv` is a balanced parentheses sequence whose
+/// first parenthesis, the super-root, encloses all others
+fn is_enclosed(bv: &BitVec64) -> bool {
+    let mut excess = 0isize;
+    for (i, bit) in bv.iter().by_vals().enumerate() {
+        excess += if bit { 1 } else { -1 };
+        if excess <= 0 && i != bv.len() - 1 {
+            return false;
+        }
+    }
+    excess == 0
+}
+
+/// Integer types that can be borrowed from little-endian bytes,
+/// every bit pattern is a valid value of these types
+trait Primitive: Copy {}
+
+impl Primitive for u64 {}
+impl Primitive for i64 {}
+impl Primitive for i16 {}
+
+struct SectionReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SectionReader<'a> {
+    fn section<T: Primitive>(&mut self, count: u64) -> Result<&'a [T], FormatError> {
+        let count = usize::try_from(count).map_err(|_| FormatError::Truncated)?;
+        let size = count
+            .checked_mul(core::mem::size_of::<T>())
+            .ok_or(FormatError::Truncated)?;
+        let section = self
+            .bytes
+            .get(self.pos..)
+            .and_then(|rest| rest.get(..size))
+            .ok_or(FormatError::Truncated)?;
+        self.pos += size.next_multiple_of(ALIGNMENT);
+        // SAFETY: the section is in bounds and starts 8-byte aligned, as the buffer
+        // is aligned and every section is padded to 8 bytes. `T` is a primitive
+        // integer with an alignment of at most 8 for which every bit pattern is valid.
+        Ok(unsafe { core::slice::from_raw_parts(section.as_ptr().cast::<T>(), count) })
+    }
+}
+
+/// A read-only [`Rmq`] that answers queries directly on serialized bytes
+///
+/// The bytes have to be written by [`Rmq::write_to`] and be aligned to 8 bytes,
+/// which holds for memory-mapped files. Nothing is copied on load, only the sizes
+/// of the stored components are validated. Indexes in another layout than
+/// [`layout::Balanced`] are loaded with [`RmqView::with_layout`].
+///
+/// ```rust,ignore
+/// let file = std::fs::File::open("index.rmq")?;
+/// let mmap = unsafe { memmap2::Mmap::map(&file)? };
+/// let rmq = RmqView::new(&mmap)?;
+/// let pos = rmq.range_minimum(2..=7);
+/// ```
+#[derive(Debug)]
+pub struct RmqView<'a, L = layout::Balanced> {
+    cartesian_tree: CartesianTree<BpRef<'a, L>>,
+}
+
+impl<'a> RmqView<'a> {
+    /// borrows the index serialized in `bytes`
+    pub fn new(bytes: &'a [u8]) -> Result<Self, FormatError> {
+        Self::with_layout(bytes)
+    }
+}
+
+impl<'a, L: Layout> RmqView<'a, L> {
+    /// borrows the index serialized in `bytes`, which has to be written in layout `L`
+    ///
+    /// the components are checked against the parentheses sequence in time linear in
+    /// the number of its words, so corrupted bytes are rejected instead of answering
+    /// queries wrongly or panicking
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "std")] {
+    /// use range_minimum_query::{layout, Rmq, RmqView};
+    ///
+    /// let rmq: Rmq<layout::Fast> = Rmq::from_iter([0,5,2,5,4,3,1,6,3]).into_layout();
+    /// let mut bytes = Vec::new();
+    /// rmq.write_to(&mut bytes).unwrap();
+    /// // a `Vec<u8>` is not guaranteed to be aligned to 8 bytes
+    /// let words: Vec<u64> = bytes
+    ///     .chunks_exact(8)
+    ///     .map(|word| u64::from_ne_bytes(word.try_into().unwrap()))
+    ///     .collect();
+    /// let bytes = unsafe { std::slice::from_raw_parts(words.as_ptr().cast::<u8>(), bytes.len()) };
+    /// let view = RmqView::<layout::Fast>::with_layout(bytes).unwrap();
+    /// assert_eq!(view.range_minimum(2..=7),Some(6));
+    /// assert!(RmqView::new(bytes).is_err());
+    /// # }
+    /// ```
+    pub fn with_layout(bytes: &'a [u8]) -> Result<Self, FormatError> {
+        Ok(Self {
+            cartesian_tree: read_bp(bytes)?,
+        })
+    }
+
+    /// returns the position of the minimum element in `range`
+    ///
+    /// returns `None` if the range is empty or not contained in the index
+    pub fn range_minimum(&self, range: impl core::ops::RangeBounds<usize>) -> Option<usize> {
+        self.try_range_minimum(range).ok()
+    }
+
+    /// returns the position of the minimum element in `range`
+    /// or the reason why the range cannot be queried
+    pub fn try_range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        self.cartesian_tree.range_minimum(range)
+    }
+
+    /// returns the number of indexed elements
+    pub fn len(&self) -> usize {
+        self.cartesian_tree.len()
+    }
+
+    /// returns `true` if the index does not contain any elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// copies the borrowed index into an owned [`Rmq`]
+    pub fn to_rmq(&self) -> Rmq<L> {
+        Rmq {
+            cartesian_tree: CartesianTree {
+                bp: self.cartesian_tree.bp.as_bp_ref().to_bp_bitvec(),
+                tie_break: self.cartesian_tree.tie_break,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    #[cfg(feature = "std")]
+    use super::RmqView;
+    #[cfg(feature = "std")]
+    use crate::TieBreak;
+    use crate::{FormatError, Rmq};
+
+    #[cfg(feature = "std")]
+    /// copies `bytes` into a buffer that starts 8-byte aligned
+    fn aligned(bytes: &[u8]) -> Vec<u64> {
+        let mut words = vec![0u64; bytes.len().div_ceil(8)];
+        for (word, chunk) in words.iter_mut().zip(bytes.chunks(8)) {
+            let mut buf = [0; 8];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            *word = u64::from_ne_bytes(buf);
+        }
+        words
+    }
+
+    #[cfg(feature = "std")]
+    fn as_bytes(words: &[u64]) -> &[u8] {
+        // SAFETY: u8 has no alignment requirement and any u64 is valid as bytes
+        unsafe { core::slice::from_raw_parts(words.as_ptr().cast::<u8>(), words.len() * 8) }
+    }
+
+    #[cfg(feature = "std")]
+    proptest! {
+        #[test]
+        fn view_matches_rmq(
+            elems in prop::collection::vec(any::<u16>(), 1..5000),
+            start in 0usize..5000,
+            len in 1usize..1000
+        ) {
+            prop_assume!(start + len <= elems.len());
+            let rmq = Rmq::from_iter(elems.iter());
+            let mut bytes = Vec::new();
+            rmq.write_to(&mut bytes).unwrap();
+            prop_assert_eq!(bytes.len() % 8, 0);
+
+            let words = aligned(&bytes);
+            let view = RmqView::new(as_bytes(&words)).unwrap();
+            prop_assert_eq!(view.len(), elems.len());
+            let range = start..(start + len);
+            prop_assert_eq!(view.range_minimum(range.clone()), rmq.range_minimum(range.clone()));
+            prop_assert_eq!(view.to_rmq().range_minimum(range.clone()), rmq.range_minimum(range));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn compact_bytes_roundtrip(
+            elems in prop::collection::vec(any::<u16>(), 0..5000),
+            start in 0usize..5000,
+            len in 1usize..1000
+        ) {
+            let rmq = Rmq::from_iter(elems.iter());
+            let bytes = rmq.to_compact_bytes();
+            prop_assert_eq!(bytes.len(), 24 + (2 * elems.len() + 2).div_ceil(64) * 8);
+
+            let loaded = Rmq::from_compact_bytes(&bytes).unwrap();
+            prop_assert_eq!(loaded.len(), elems.len());
+            let range = start..(start + len);
+            prop_assert_eq!(loaded.range_minimum(range.clone()), rmq.range_minimum(range));
+        }
+    }
+
+    #[cfg(feature = "std")]
+    proptest! {
+        #[test]
+        fn streamed_compact_bytes_match(
+            elems in prop::collection::vec(0u8..8, 0..3000),
+            rightmost in any::<bool>(),
+            buffer_words in 1usize..8
+        ) {
+            let tie_break = if rightmost { TieBreak::Rightmost } else { TieBreak::Leftmost };
+            let rmq = Rmq::from_iter_with_tie_break(elems.iter(), tie_break);
+
+            // the index does not have to start at the beginning of the writer
+            let mut writer = std::io::Cursor::new(vec![7u8; 5]);
+            writer.set_position(5);
+            super::write_compact_buffered(elems.iter().copied(), u8::cmp, tie_break, &mut writer, buffer_words).unwrap();
+            prop_assert_eq!(writer.position() as usize, writer.get_ref().len());
+            prop_assert_eq!(&writer.get_ref()[5..], &rmq.to_compact_bytes()[..]);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    proptest! {
+        #[test]
+        fn corrupted_view_is_rejected(
+            elems in prop::collection::vec(any::<u16>(), 1..5000),
+            word in any::<prop::sample::Index>(),
+            bit in 0u32..64
+        ) {
+            let rmq = Rmq::from_iter(elems.iter());
+            let mut bytes = Vec::new();
+            rmq.write_to(&mut bytes).unwrap();
+            let mut words = aligned(&bytes);
+            // flip a bit in one of the components behind the header
+            let word = super::HEADER_SIZE / 8 + word.index(words.len() - super::HEADER_SIZE / 8);
+            words[word] ^= 1 << bit;
+
+            // only the padding behind the last component goes unnoticed
+            match RmqView::new(as_bytes(&words)) {
+                Ok(view) => {
+                    for end in 1..=elems.len() {
+                        prop_assert_eq!(view.range_minimum(..end), rmq.range_minimum(..end));
+                    }
+                }
+                Err(err) => prop_assert_eq!(err, FormatError::InvalidLayout),
+            }
+        }
+    }
+
+    /// yields the elements of a range but claims another length
+    #[cfg(feature = "std")]
+    struct ClaimedLength(core::ops::Range<u8>, usize);
+
+    #[cfg(feature = "std")]
+    impl Iterator for ClaimedLength {
+        type Item = u8;
+
+        fn next(&mut self) -> Option<u8> {
+            self.0.next()
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.1, Some(self.1))
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl ExactSizeIterator for ClaimedLength {}
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn streamed_compact_checks_length() {
+        for claimed in [99, 101] {
+            let mut writer = std::io::Cursor::new(Vec::new());
+            let err = super::write_compact_buffered(
+                ClaimedLength(0..100, claimed),
+                u8::cmp,
+                TieBreak::Leftmost,
+                &mut writer,
+                1,
+            )
+            .unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+
+    /// fails every write that reaches past `limit` bytes
+    #[cfg(feature = "std")]
+    struct LimitedWriter(std::io::Cursor<Vec<u8>>, u64);
+
+    #[cfg(feature = "std")]
+    impl std::io::Write for LimitedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.0.position() + buf.len() as u64 > self.1 {
+                return Err(std::io::ErrorKind::WriteZero.into());
+            }
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl std::io::Seek for LimitedWriter {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn streamed_compact_stops_after_write_error() {
+        let mut read = 0;
+        let values = (0..10_000u32).inspect(|_| read += 1);
+        // only the header fits
+        let mut writer = LimitedWriter(std::io::Cursor::new(Vec::new()), 24);
+        let err =
+            super::write_compact_buffered(values, u32::cmp, TieBreak::Leftmost, &mut writer, 1)
+                .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WriteZero);
+        // the first word is written after about 32 values
+        assert!(read < 100, "read {read} values");
+    }
+
+    #[test]
+    fn invalid_compact_data_is_rejected() {
+        let rmq = Rmq::from_iter([3, 1, 4, 1, 5, 9, 2, 6]);
+        let bytes = rmq.to_compact_bytes();
+
+        assert!(Rmq::from_compact_bytes(&bytes).is_ok());
+        assert_eq!(
+            Rmq::from_compact_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            FormatError::Truncated
+        );
+        assert_eq!(
+            Rmq::from_compact_bytes(&bytes[1..]).unwrap_err(),
+            FormatError::InvalidMagic
+        );
+
+        let mut unbalanced = bytes.clone();
+        unbalanced[24] ^= 1;
+        assert_eq!(
+            Rmq::from_compact_bytes(&unbalanced).unwrap_err(),
+            FormatError::InvalidLayout
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn layout_is_stored_in_header() {
+        use crate::layout;
+
+        let rmq: Rmq<layout::Fast> = Rmq::from_iter([3, 1, 4, 1, 5, 9, 2, 6]).into_layout();
+        let mut bytes = Vec::new();
+        rmq.write_to(&mut bytes).unwrap();
+        let words = aligned(&bytes);
+        let bytes = as_bytes(&words);
+
+        let view = RmqView::<layout::Fast>::with_layout(bytes).unwrap();
+        assert_eq!(view.range_minimum(2..), Some(3));
+        assert_eq!(view.to_rmq().range_minimum(2..), Some(3));
+        assert_eq!(
+            RmqView::new(bytes).unwrap_err(),
+            FormatError::LayoutMismatch
+        );
+        assert_eq!(
+            RmqView::<layout::Compact>::with_layout(bytes).unwrap_err(),
+            FormatError::LayoutMismatch
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn invalid_data_is_rejected() {
+        let rmq = Rmq::from_iter([3, 1, 4, 1, 5, 9, 2, 6]);
+        let mut bytes = Vec::new();
+        rmq.write_to(&mut bytes).unwrap();
+        let words = aligned(&bytes);
+        let bytes = as_bytes(&words);
+
+        assert!(RmqView::new(bytes).is_ok());
+        assert_eq!(
+            RmqView::new(&bytes[..bytes.len() - 8]).unwrap_err(),
+            FormatError::Truncated
+        );
+        assert_eq!(
+            RmqView::new(&bytes[8..]).unwrap_err(),
+            FormatError::InvalidMagic
+        );
+
+        let mut shifted = vec![0u8];
+        shifted.extend_from_slice(bytes);
+        let shifted_words = aligned(&shifted);
+        assert_eq!(
+            RmqView::new(&as_bytes(&shifted_words)[1..]).unwrap_err(),
+            FormatError::Misaligned
+        );
+
+        let mut other_version = words.clone();
+        other_version[1] = u64::from_ne_bytes([2, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            RmqView::new(as_bytes(&other_version)).unwrap_err(),
+            FormatError::UnsupportedVersion(2)
+        );
+
+        let mut bad_layout = words;
+        bad_layout[3] += 1;
+        assert_eq!(
+            RmqView::new(as_bytes(&bad_layout)).unwrap_err(),
+            FormatError::InvalidLayout
+        );
+    }
+}
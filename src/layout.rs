@@ -0,0 +1,110 @@
+//! Block sizes of the succinct index, trading space for query time
+//!
+//! The balanced parentheses sequence takes `2n` bits for `n` elements in every layout,
+//! the layouts differ in how densely the auxiliary structures sample it. Smaller blocks
+//! store more excess minima and `select0` hints, so queries scan fewer words.
+//!
+//! | layout       | words per block | blocks per superblock | zeros per `select0` hint |
+//! |--------------|-----------------|-----------------------|--------------------------|
+//! | [`Compact`]  | 8               | 32                    | 4096                     |
+//! | [`Balanced`] | 4               | 32                    | 1024                     |
+//! | [`Fast`]     | 2               | 16                    | 512                      |
+//!
+//! The rank directory is shared by all layouts, its blocks of 8 words are fixed by
+//! the packing of 7 sub-block ranks of 9 bits into a single word.
+//!
+//! Measured by `cargo bench --bench layout` on 10 million random values and one million
+//! random ranges, on one core of an Intel Xeon (Sapphire Rapids) KVM guest. The query
+//! times are the medians of three runs, which varied by up to 25% on this machine:
+//!
+//! | layout       | bits per element | 1M queries | build  |
+//! |--------------|------------------|------------|--------|
+//! | [`Compact`]  | 2.60             | 1.00 s     | 0.52 s |
+//! | [`Balanced`] | 2.73             | 0.82 s     | 0.54 s |
+//! | [`Fast`]     | 3.04             | 0.80 s     | 0.54 s |
+//!
+//! ```rust
+//! use range_minimum_query::{layout, Rmq, RmqBuilder};
+//!
+//! let mut builder = RmqBuilder::new().with_layout::<layout::Fast>();
+//! builder.extend([0,5,2,5,4,3,1,6,3]);
+//! let fast: Rmq<layout::Fast> = builder.finish();
+//! assert_eq!(fast.range_minimum(2..=7),Some(6));
+//!
+//! // an existing index keeps its parentheses and rebuilds the other structures
+//! let compact: Rmq<layout::Compact> = fast.into_layout();
+//! assert_eq!(compact.range_minimum(2..=7),Some(6));
+//! ```
+
+/// The block sizes of the auxiliary structures of the index
+///
+/// The trait is sealed, use one of the presets of this module.
+pub trait Layout: sealed::Sealed + core::fmt::Debug + Clone + Copy + Send + Sync + 'static {
+    /// number of 64 bit words per block, the excess minimum is stored for every block
+    const BLOCK_WORDS: usize;
+    /// number of blocks per superblock, the leaves of the min-tree
+    const SUPERBLOCK_BLOCKS: usize;
+    /// number of closing parentheses between two `select0` hints
+    const ZEROS_PER_HINT: usize;
+}
+
+pub(crate) mod sealed {
+    pub trait Sealed {
+        /// identifies the layout in the binary format
+        const ID: u32;
+    }
+}
+
+/// Large blocks and sparse `select0` hints for the smallest index
+#[derive(Debug, Clone, Copy)]
+pub struct Compact;
+
+/// The layout used unless another one is selected
+#[derive(Debug, Clone, Copy)]
+pub struct Balanced;
+
+/// Small blocks and dense `select0` hints for the fastest queries
+#[derive(Debug, Clone, Copy)]
+pub struct Fast;
+
+impl Layout for Compact {
+    const BLOCK_WORDS: usize = 8;
+    const SUPERBLOCK_BLOCKS: usize = 32;
+    const ZEROS_PER_HINT: usize = 4096;
+}
+
+impl Layout for Balanced {
+    const BLOCK_WORDS: usize = 4;
+    const SUPERBLOCK_BLOCKS: usize = 32;
+    const ZEROS_PER_HINT: usize = 1024;
+}
+
+impl Layout for Fast {
+    const BLOCK_WORDS: usize = 2;
+    const SUPERBLOCK_BLOCKS: usize = 16;
+    const ZEROS_PER_HINT: usize = 512;
+}
+
+// existing files without a layout id were written with the default layout
+impl sealed::Sealed for Balanced {
+    const ID: u32 = 0;
+}
+
+impl sealed::Sealed for Compact {
+    const ID: u32 = 1;
+}
+
+impl sealed::Sealed for Fast {
+    const ID: u32 = 2;
+}
+
+const _: () = {
+    /// the block excess minima are stored as `i16` relative to their superblock,
+    /// and a rank block must not pass two `select0` hints
+    const fn is_valid<L: Layout>() -> bool {
+        L::BLOCK_WORDS * L::SUPERBLOCK_BLOCKS * 64 <= 1 << 15
+            && L::ZEROS_PER_HINT >= crate::bp::RANK_BLOCK_SIZE * 64
+    }
+
+    assert!(is_valid::<Compact>() && is_valid::<Balanced>() && is_valid::<Fast>());
+};
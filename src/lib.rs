@@ -1,3 +1,4 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![warn(missing_debug_implementations, missing_docs, rust_2018_idioms)]
 //!
 //! # Range Minimum Query (RMQ)
@@ -28,25 +29,621 @@
 //! let res = rmq.range_minimum(2..=7);
 //! assert_eq!(res.unwrap(),6);
 //! ```
+//!
+//! Range maximum queries are supported by [`RangeMaxQuery`], and [`MinMaxRmq`]
+//! answers both kinds of queries from indexes built in a single pass:
+//!
+//! ```rust
+//! use range_minimum_query::MinMaxRmq;
+//!
+//! let rmq = MinMaxRmq::from_iter([0,5,2,5,4,3,1,6,3]);
+//! assert_eq!(rmq.range_min_max(2..=7),Some((6,7)));
+//! ```
+//!
+//! To query minimum values instead of positions, keep the values next to the index:
+//!
+//! ```rust
+//! use range_minimum_query::RmqWithValues;
+//!
+//! let rmq = RmqWithValues::new(vec![0,5,2,5,4,3,1,6,3]);
+//! assert_eq!(rmq.min_entry(2..=7),Some((6,&1)));
+//! assert_eq!(rmq.min_value(1..4),Some(&2));
+//! ```
+//!
+//! [`SparseTableRmq`] and [`BlockRmq`] answer queries faster by keeping the values
+//! and more words per element, all indexes implement the [`RangeMinimum`] trait.
+//! [`DynamicRmq`] supports updates, insertions and deletions of values.
+//!
+//! The [`layout`] module selects the block sizes of the index,
+//! trading space for query time:
+//!
+//! ```rust
+//! use range_minimum_query::{layout, Rmq, RmqBuilder};
+//!
+//! let mut builder = RmqBuilder::new().with_layout::<layout::Compact>();
+//! builder.extend([0,5,2,5,4,3,1,6,3]);
+//! let rmq: Rmq<layout::Compact> = builder.finish();
+//! assert_eq!(rmq.range_minimum(2..=7),Some(6));
+//! ```
+//!
+//! # Nearest smaller values
+//!
+//! The Cartesian tree of an index also finds the closest smaller element left and
+//! right of a position, but each index answers only two of the four variants.
+//! An index built with [`TieBreak::Leftmost`], the default, cannot tell an equal
+//! element on the left from a larger one, so it answers [`Rmq::next_smaller`] and
+//! [`Rmq::previous_smaller_or_equal`]. [`TieBreak::Rightmost`] answers the mirrored
+//! pair, and both strictly smaller neighbours need one index of each:
+//!
+//! ```rust
+//! use range_minimum_query::{Rmq, RmqError, TieBreak};
+//!
+//! let a = [1, 3, 2, 3, 2];
+//! let leftmost = Rmq::from_iter(a);
+//! let rightmost = Rmq::from_iter_with_tie_break(a, TieBreak::Rightmost);
+//! assert_eq!(leftmost.next_smaller(1), Ok(Some(2)));
+//! assert_eq!(leftmost.previous_smaller_or_equal(4), Ok(Some(2)));
+//! assert_eq!(rightmost.previous_smaller(4), Ok(Some(0)));
+//! assert_eq!(rightmost.next_smaller_or_equal(2), Ok(Some(4)));
+//! assert_eq!(
+//!     leftmost.previous_smaller(4),
+//!     Err(RmqError::WrongTieBreak { required: TieBreak::Rightmost })
+//! );
+//! ```
+//!
+//! # Features
+//!
+//! - `std` (enabled by default) adds [`Rmq::write_to`] and the [`ExternalRmqBuilder`]
+//!   for inputs larger than memory. Without it the crate is `#![no_std]`
+//!   and only depends on `alloc`.
+//! - `serde` derives `Serialize` and `Deserialize` for the indexes.
+//! - `rayon` adds parallel construction and batch queries, it requires `std`.
 
+extern crate alloc;
+
+mod balanced_parentheses;
+mod block_rmq;
 mod bp;
+mod builder;
 mod cartesian_tree;
+mod dynamic;
+mod error;
+#[cfg(feature = "std")]
+mod external;
+mod format;
+pub mod layout;
+mod max;
+mod range_minimum;
+mod sparse_table;
 mod util;
+mod with_values;
 
+use alloc::vec::Vec;
+pub use balanced_parentheses::BalancedParentheses;
+pub use block_rmq::BlockRmq;
+use bp::BpBitVec;
+pub use bp::{ExcessBackend, SpaceBreakdown};
+pub use builder::RmqBuilder;
 use cartesian_tree::CartesianTree;
+pub use cartesian_tree::TieBreak;
+pub use dynamic::DynamicRmq;
+pub use error::{FormatError, RmqError};
+#[cfg(feature = "std")]
+pub use external::{ExternalRmqBuilder, ExternalValue};
+pub use format::RmqView;
+use layout::Layout;
+pub use max::{MinMaxRmq, RangeMaxQuery};
+pub use range_minimum::RangeMinimum;
+pub use sparse_table::SparseTableRmq;
+pub use util::SelectBackend;
+pub use with_values::{Minima, RmqWithValues};
 
 /// The main RMQ data structure
+///
+/// The block sizes of the index are selected by the [`Layout`] `L`. The constructors
+/// of `Rmq` build indexes in [`layout::Balanced`], other layouts are built by
+/// [`RmqBuilder::with_layout`] or converted with [`Rmq::into_layout`].
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct Rmq {
-    cartesian_tree: CartesianTree,
+#[cfg_attr(feature = "serde", serde(bound = "L: Layout"))]
+pub struct Rmq<L = layout::Balanced> {
+    cartesian_tree: CartesianTree<BpBitVec<L>>,
 }
 
-impl Rmq {
+impl<L: Layout> Rmq<L> {
+    /// returns the position of the minimum element in `range`
+    ///
+    /// if the minimum occurs more than once, the position is selected by the
+    /// [`TieBreak`] the index was built with, [`TieBreak::Leftmost`] by default.
+    /// returns `None` if the range is empty or not contained in the index
+    pub fn range_minimum(&self, range: impl core::ops::RangeBounds<usize>) -> Option<usize> {
+        self.try_range_minimum(range).ok()
+    }
+
     /// returns the position of the minimum element in `range`
-    pub fn range_minimum(&self, range: impl std::ops::RangeBounds<usize>) -> Option<usize> {
+    /// or the reason why the range cannot be queried
+    pub fn try_range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
         self.cartesian_tree.range_minimum(range)
     }
+
+    /// answers the range minimum queries in `queries`, writing the position
+    /// of the minimum of `queries[i]` to `out[i]`
+    ///
+    /// The start positions and then the end positions of the queries are visited
+    /// in sorted order, so queries that share a start or an end position share its
+    /// `select0` lookups, and the lookups walk the index front to back.
+    /// Returns the error of the first invalid query, in which case `out` is unchanged.
+    ///
+    /// # Panics
+    ///
+    /// if `queries` and `out` differ in length
+    ///
+    /// ```rust
+    /// use range_minimum_query::Rmq;
+    ///
+    /// let rmq = Rmq::from_iter([5, 2, 8, 1, 9]);
+    /// let mut out = [0; 3];
+    /// rmq.range_minimum_batch(&[0..3, 2..5, 4..5], &mut out).unwrap();
+    /// assert_eq!(out, [1, 3, 4]);
+    /// ```
+    pub fn range_minimum_batch(
+        &self,
+        queries: &[core::ops::Range<usize>],
+        out: &mut [usize],
+    ) -> Result<(), RmqError> {
+        self.cartesian_tree.range_minimum_batch(queries, out)
+    }
+
+    /// answers the queries like [`Rmq::range_minimum_batch`]
+    /// using the rayon thread pool
+    #[cfg(feature = "rayon")]
+    pub fn par_range_minimum_batch(
+        &self,
+        queries: &[core::ops::Range<usize>],
+        out: &mut [usize],
+    ) -> Result<(), RmqError> {
+        use rayon::prelude::*;
+
+        const CHUNK_SIZE: usize = 1 << 14;
+        assert_eq!(
+            queries.len(),
+            out.len(),
+            "every query needs a position in the output"
+        );
+        // check all queries before the chunks write their answers to `out`
+        if let Some(Err(err)) = queries
+            .par_iter()
+            .map(|query| self.cartesian_tree.resolve_range(query.clone()))
+            .find_first(Result::is_err)
+        {
+            return Err(err);
+        }
+        queries
+            .par_chunks(CHUNK_SIZE)
+            .zip(out.par_chunks_mut(CHUNK_SIZE))
+            .try_for_each(|(queries, out)| self.range_minimum_batch(queries, out))
+    }
+
+    /// returns the positions of the `k` smallest elements in `range`
+    /// in non-decreasing order of their values
+    ///
+    /// `values` are the values the index was built from, they order the subranges left
+    /// after removing the minima. Every position costs one `log k` step on a heap and
+    /// two range minimum queries, which are only answered when the next position is
+    /// requested. Equal values are returned in order of their positions, or in reverse
+    /// order if the index was built with [`TieBreak::Rightmost`]. Fewer than `k` positions
+    /// are returned if the range is shorter and none if it cannot be queried.
+    ///
+    /// # Panics
+    ///
+    /// if `values` is shorter than the index
+    ///
+    /// ```rust
+    /// use range_minimum_query::Rmq;
+    ///
+    /// let a = [0,5,2,5,4,3,1,6,3];
+    /// let rmq = Rmq::from_iter(a);
+    /// let smallest: Vec<usize> = rmq.top_k_minima(2..=7, 3, &a).collect();
+    /// assert_eq!(smallest,[6,2,5]);
+    /// ```
+    pub fn top_k_minima<'a, T: Ord>(
+        &'a self,
+        range: impl core::ops::RangeBounds<usize>,
+        k: usize,
+        values: &'a [T],
+    ) -> impl Iterator<Item = usize> + 'a {
+        assert!(
+            values.len() >= self.len(),
+            "the values have to cover the index"
+        );
+        Minima::new(self, values, range).take(k).map(|(pos, _)| pos)
+    }
+
+    /// returns the [`TieBreak`] the index was built with
+    pub fn tie_break(&self) -> TieBreak {
+        self.cartesian_tree.tie_break
+    }
+
+    /// returns the number of indexed elements
+    pub fn len(&self) -> usize {
+        self.cartesian_tree.len()
+    }
+
+    /// returns `true` if the index does not contain any elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// returns the position of the root of the Cartesian tree,
+    /// the minimum of all elements
+    pub fn root(&self) -> Option<usize> {
+        self.range_minimum(..)
+    }
+
+    /// returns the parent of position `i` in the Cartesian tree
+    ///
+    /// ```rust
+    /// use range_minimum_query::Rmq;
+    ///
+    /// //       1
+    /// //     /   \
+    /// //    2     3
+    /// //     \
+    /// //      4
+    /// let rmq = Rmq::from_iter([2,4,1,3]);
+    /// assert_eq!(rmq.root(),Some(2));
+    /// assert_eq!(rmq.parent(1),Some(0));
+    /// assert_eq!(rmq.parent(2),None);
+    /// assert_eq!(rmq.left_child(2),Some(0));
+    /// assert_eq!(rmq.right_child(0),Some(1));
+    /// assert_eq!(rmq.lca(1,3),Some(2));
+    /// assert_eq!(rmq.subtree_range(0),Some(0..=1));
+    /// ```
+    pub fn parent(&self, i: usize) -> Option<usize> {
+        self.cartesian_tree.parent(i)
+    }
+
+    /// returns the left child of position `i` in the Cartesian tree,
+    /// the minimum of the elements left of `i` in its subtree
+    pub fn left_child(&self, i: usize) -> Option<usize> {
+        self.cartesian_tree.left_child(i)
+    }
+
+    /// returns the right child of position `i` in the Cartesian tree,
+    /// the minimum of the elements right of `i` in its subtree
+    pub fn right_child(&self, i: usize) -> Option<usize> {
+        self.cartesian_tree.right_child(i)
+    }
+
+    /// returns the lowest common ancestor of the positions `i` and `j`
+    /// in the Cartesian tree, which is the minimum of `i..=j`
+    pub fn lca(&self, i: usize, j: usize) -> Option<usize> {
+        self.cartesian_tree.lca(i, j)
+    }
+
+    /// returns the positions in the subtree of `i` in the Cartesian tree,
+    /// the maximal range for which `i` is reported as the minimum
+    pub fn subtree_range(&self, i: usize) -> Option<core::ops::RangeInclusive<usize>> {
+        self.cartesian_tree.subtree_range(i)
+    }
+
+    /// returns the closest position left of `i` whose element is strictly smaller
+    ///
+    /// returns [`RmqError::WrongTieBreak`] if the index was built with
+    /// [`TieBreak::Leftmost`], as its Cartesian tree does not distinguish equal elements
+    /// from larger ones on the left (see [nearest smaller values](crate#nearest-smaller-values)),
+    /// and [`RmqError::OutOfBounds`] if `i` is not an indexed position
+    pub fn previous_smaller(&self, i: usize) -> Result<Option<usize>, RmqError> {
+        self.check_smaller_query(i, TieBreak::Rightmost)?;
+        Ok(self.cartesian_tree.prev_winner(i))
+    }
+
+    /// returns the closest position left of `i` whose element is smaller or equal
+    ///
+    /// returns [`RmqError::WrongTieBreak`] if the index was built with [`TieBreak::Rightmost`]
+    /// and [`RmqError::OutOfBounds`] if `i` is not an indexed position
+    pub fn previous_smaller_or_equal(&self, i: usize) -> Result<Option<usize>, RmqError> {
+        self.check_smaller_query(i, TieBreak::Leftmost)?;
+        Ok(self.cartesian_tree.prev_winner(i))
+    }
+
+    /// returns the closest position right of `i` whose element is strictly smaller
+    ///
+    /// returns [`RmqError::WrongTieBreak`] if the index was built with [`TieBreak::Rightmost`]
+    /// and [`RmqError::OutOfBounds`] if `i` is not an indexed position
+    pub fn next_smaller(&self, i: usize) -> Result<Option<usize>, RmqError> {
+        self.check_smaller_query(i, TieBreak::Leftmost)?;
+        Ok(self.cartesian_tree.next_winner(i))
+    }
+
+    /// returns the closest position right of `i` whose element is smaller or equal
+    ///
+    /// returns [`RmqError::WrongTieBreak`] if the index was built with [`TieBreak::Leftmost`]
+    /// and [`RmqError::OutOfBounds`] if `i` is not an indexed position
+    pub fn next_smaller_or_equal(&self, i: usize) -> Result<Option<usize>, RmqError> {
+        self.check_smaller_query(i, TieBreak::Rightmost)?;
+        Ok(self.cartesian_tree.next_winner(i))
+    }
+
+    fn check_smaller_query(&self, i: usize, required: TieBreak) -> Result<(), RmqError> {
+        if self.tie_break() != required {
+            return Err(RmqError::WrongTieBreak { required });
+        }
+        self.cartesian_tree.resolve_range(i..=i).map(|_| ())
+    }
+
+    /// writes the index in the versioned binary layout that can be
+    /// queried in place by [`RmqView`]
+    ///
+    /// the index is written with many small writes, so `writer` should be buffered
+    ///
+    /// ```rust
+    /// use range_minimum_query::Rmq;
+    ///
+    /// let rmq = Rmq::from_iter([0,5,2,5,4,3,1,6,3]);
+    /// let mut bytes = Vec::new();
+    /// rmq.write_to(&mut bytes).unwrap();
+    /// assert_eq!(bytes.len() % 8, 0);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn write_to(&self, writer: impl std::io::Write) -> std::io::Result<()> {
+        format::write_bp(&self.cartesian_tree, writer)
+    }
+
+    /// returns the balanced parentheses sequence of the index with a small header,
+    /// about `2n` bits for `n` elements
+    ///
+    /// the auxiliary rank, select and excess structures are not stored
+    /// but rebuilt by [`Rmq::from_compact_bytes`]
+    ///
+    /// ```rust
+    /// use range_minimum_query::Rmq;
+    ///
+    /// let rmq = Rmq::from_iter([0,5,2,5,4,3,1,6,3]);
+    /// let bytes = rmq.to_compact_bytes();
+    /// let rmq = Rmq::from_compact_bytes(&bytes).unwrap();
+    /// assert_eq!(rmq.range_minimum(2..=7),Some(6));
+    /// ```
+    pub fn to_compact_bytes(&self) -> Vec<u8> {
+        format::write_compact_bp(&self.cartesian_tree)
+    }
+
+    /// returns the heap memory used by the index in bytes
+    pub fn heap_size_in_bytes(&self) -> usize {
+        self.space_breakdown().total_bytes()
+    }
+
+    /// returns the heap memory used by each component of the index
+    ///
+    /// ```rust
+    /// use range_minimum_query::Rmq;
+    ///
+    /// let rmq = Rmq::from_iter(0..100_000u32);
+    /// let space = rmq.space_breakdown();
+    /// assert_eq!(space.total_bytes(),rmq.heap_size_in_bytes());
+    /// assert!(space.bits_per_element() < 3.0);
+    /// ```
+    pub fn space_breakdown(&self) -> SpaceBreakdown {
+        self.cartesian_tree.space_breakdown()
+    }
+
+    /// rebuilds the auxiliary structures of the index in layout `M`,
+    /// the balanced parentheses sequence is moved and not copied
+    ///
+    /// ```rust
+    /// use range_minimum_query::{layout, Rmq};
+    ///
+    /// let rmq = Rmq::from_iter(0..100_000u32);
+    /// let compact: Rmq<layout::Compact> = rmq.into_layout();
+    /// assert_eq!(compact.range_minimum(10..),Some(10));
+    /// assert!(compact.space_breakdown().bits_per_element() < 2.7);
+    /// ```
+    pub fn into_layout<M: Layout>(self) -> Rmq<M> {
+        Rmq {
+            cartesian_tree: self.cartesian_tree.into_layout(),
+        }
+    }
+}
+
+impl Rmq {
+    /// builds the index over `values` using the rayon thread pool
+    ///
+    /// The slice is split into chunks whose Cartesian trees are built in parallel
+    /// and merged along their right spines, the result is identical to `from_iter`.
+    #[cfg(feature = "rayon")]
+    pub fn par_from_slice<T: Ord + Sync>(values: &[T]) -> Self {
+        Self {
+            cartesian_tree: CartesianTree::par_from_slice_by(values, T::cmp, TieBreak::Leftmost),
+        }
+    }
+
+    /// builds the index and selects which position of a repeated
+    /// minimum is reported by the queries
+    ///
+    /// ```rust
+    /// use range_minimum_query::{Rmq, TieBreak};
+    ///
+    /// let a = [2,1,3,1,4];
+    /// let leftmost = Rmq::from_iter_with_tie_break(a, TieBreak::Leftmost);
+    /// let rightmost = Rmq::from_iter_with_tie_break(a, TieBreak::Rightmost);
+    /// assert_eq!(leftmost.range_minimum(..),Some(1));
+    /// assert_eq!(rightmost.range_minimum(..),Some(3));
+    /// ```
+    pub fn from_iter_with_tie_break<T, I>(iter: I, tie_break: TieBreak) -> Self
+    where
+        T: Ord,
+        I: IntoIterator<Item = T>,
+    {
+        Self {
+            cartesian_tree: CartesianTree::from_iter_by(iter, T::cmp, tie_break),
+        }
+    }
+
+    /// builds the index using the comparator function `compare`,
+    /// like [`slice::sort_by`]
+    ///
+    /// ```rust
+    /// use range_minimum_query::Rmq;
+    ///
+    /// let words = ["pear", "Apple", "banana"];
+    /// let rmq = Rmq::from_iter_by(words, |a, b| a.to_lowercase().cmp(&b.to_lowercase()));
+    /// assert_eq!(rmq.range_minimum(..),Some(1));
+    /// ```
+    pub fn from_iter_by<T, I, F>(iter: I, compare: F) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        F: FnMut(&T, &T) -> core::cmp::Ordering,
+    {
+        Self {
+            cartesian_tree: CartesianTree::from_iter_by(iter, compare, TieBreak::Leftmost),
+        }
+    }
+
+    /// builds the index over the values of an iterator of known length
+    ///
+    /// every parenthesis is written to its final position, which saves the pass
+    /// over the whole sequence that reverses it after [`Rmq::from_iter`]
+    ///
+    /// # Panics
+    ///
+    /// if the iterator yields a different number of values than its length
+    ///
+    /// ```rust
+    /// use range_minimum_query::Rmq;
+    ///
+    /// let a = vec![0,5,2,5,4,3,1,6,3];
+    /// let rmq = Rmq::from_exact_size_iter(&a);
+    /// assert_eq!(rmq.range_minimum(2..=7),Some(6));
+    /// ```
+    pub fn from_exact_size_iter<T, I>(iter: I) -> Self
+    where
+        T: Ord,
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        Self {
+            cartesian_tree: CartesianTree::from_exact_size_iter_by(
+                iter,
+                T::cmp,
+                TieBreak::Leftmost,
+            ),
+        }
+    }
+
+    /// builds the index over `values`
+    ///
+    /// only positions are kept on the stack of the construction, as `u32` if there
+    /// are fewer than `2^32` values, and compared through the slice. This saves memory
+    /// for large values like strings, which [`Rmq::from_iter`] keeps on its stack.
+    ///
+    /// ```rust
+    /// use range_minimum_query::Rmq;
+    ///
+    /// let words = ["pear".to_string(), "apple".to_string(), "banana".to_string()];
+    /// let rmq = Rmq::from_slice(&words);
+    /// assert_eq!(rmq.range_minimum(..),Some(1));
+    /// ```
+    pub fn from_slice<T: Ord>(values: &[T]) -> Self {
+        Self {
+            cartesian_tree: CartesianTree::from_slice_by(values, T::cmp, TieBreak::Leftmost),
+        }
+    }
+
+    /// builds the index over borrowed values, which are compared
+    /// in place and never cloned
+    ///
+    /// this is the same as [`Rmq::from_iter`] over the references, which are `Ord`
+    /// themselves, and only spells out that the values are not cloned
+    ///
+    /// ```rust
+    /// use range_minimum_query::Rmq;
+    ///
+    /// let words = vec!["pear".to_string(), "apple".to_string(), "banana".to_string()];
+    /// let rmq = Rmq::from_iter_ref(words.iter().filter(|w| w.len() > 4));
+    /// assert_eq!(rmq.range_minimum(..),Some(0));
+    ///
+    /// // unsized values such as `str` slices work as well
+    /// let rmq = Rmq::from_iter_ref("pear apple banana".split(' '));
+    /// assert_eq!(rmq.range_minimum(..),Some(1));
+    /// ```
+    pub fn from_iter_ref<'a, T, I>(iter: I) -> Self
+    where
+        T: Ord + ?Sized + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        Self::from_iter(iter)
+    }
+
+    /// writes the index over the values of an iterator of known length
+    /// in the format of [`Rmq::to_compact_bytes`], without keeping it in memory
+    ///
+    /// The balanced parentheses sequence is written from back to front while the
+    /// values are read, so only the values on the right spine of the Cartesian tree
+    /// and a buffer of bits are kept in memory. The index starts at the current
+    /// position of `writer`, which is left at its end.
+    ///
+    /// The words are written in decreasing order of their positions, seeking back
+    /// before every buffer, so `writer` has to be seekable like a file or a cursor.
+    /// Pipes and sockets cannot be written to this way;
+    /// [`ExternalRmqBuilder`] writes the index to them in order.
+    ///
+    /// # Errors
+    ///
+    /// returns the errors of `writer` and an error of kind
+    /// [`InvalidInput`](std::io::ErrorKind::InvalidInput) if the iterator yields
+    /// a different number of values than its length, the bytes written so far
+    /// are not a valid index then
+    ///
+    /// ```rust
+    /// use range_minimum_query::Rmq;
+    ///
+    /// let mut file = std::io::Cursor::new(Vec::new());
+    /// Rmq::write_compact_from_iter([0,5,2,5,4,3,1,6,3], &mut file).unwrap();
+    /// let rmq = Rmq::from_compact_bytes(file.get_ref()).unwrap();
+    /// assert_eq!(rmq.range_minimum(2..=7),Some(6));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn write_compact_from_iter<T, I, W>(iter: I, writer: W) -> std::io::Result<()>
+    where
+        T: Ord,
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+        W: std::io::Write + std::io::Seek,
+    {
+        format::write_compact_from_iter(iter, T::cmp, TieBreak::Leftmost, writer)
+    }
+
+    /// builds the index over the keys extracted by `key`,
+    /// like [`slice::sort_by_key`]
+    ///
+    /// the key is extracted once per element
+    pub fn from_iter_by_key<T, K, I, F>(iter: I, mut key: F) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        K: Ord,
+        F: FnMut(&T) -> K,
+    {
+        Self::from_iter(iter.into_iter().map(|item| key(&item)))
+    }
+
+    /// builds the index over floating point values ordered by [`f64::total_cmp`]
+    ///
+    /// under this order `-0.0` is smaller than `0.0`, negative NaNs are smaller
+    /// and positive NaNs are larger than all other values
+    pub fn from_f64_total_order<I: IntoIterator<Item = f64>>(iter: I) -> Self {
+        Self::from_iter_by(iter, f64::total_cmp)
+    }
+
+    /// loads an index written by [`Rmq::to_compact_bytes`] and
+    /// rebuilds its auxiliary structures
+    pub fn from_compact_bytes(bytes: &[u8]) -> Result<Self, FormatError> {
+        Ok(Self {
+            cartesian_tree: format::read_compact_bp(bytes)?,
+        })
+    }
 }
 
 impl<T: Ord> FromIterator<T> for Rmq {
@@ -104,7 +701,7 @@ mod tests {
         ) {
             prop_assume!(start + len < elems.len());
             let other = elems.clone();
-            let rmq = super::Rmq::from_iter(other.into_iter().map(std::cmp::Reverse));
+            let rmq = super::Rmq::from_iter(other.into_iter().map(core::cmp::Reverse));
 
             let maxpos = rmq.range_minimum(start..(start+len)).unwrap();
 
@@ -129,4 +726,356 @@ mod tests {
             }
         }
     }
+
+    proptest! {
+        #[test]
+        fn layouts_agree(
+            elems in prop::collection::vec(any::<u8>(), 1..40_000),
+            queries in prop::collection::vec((any::<prop::sample::Index>(), any::<prop::sample::Index>()), 1..100)
+        ) {
+            use super::{layout, Rmq};
+
+            let rmq = Rmq::from_iter(elems.iter());
+            let compact: Rmq<layout::Compact> = Rmq::from_iter(elems.iter()).into_layout();
+            let fast: Rmq<layout::Fast> = Rmq::from_iter(elems.iter()).into_layout();
+            for (start, end) in queries {
+                let (start, end) = (start.index(elems.len()), end.index(elems.len()));
+                let range = start.min(end)..=start.max(end);
+                let expected = rmq.range_minimum(range.clone());
+                prop_assert_eq!(compact.range_minimum(range.clone()), expected);
+                prop_assert_eq!(fast.range_minimum(range), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_ranges_are_reported() {
+        use super::RmqError;
+        use core::ops::Bound;
+
+        let rmq = super::Rmq::from_iter([3, 1, 2]);
+        assert_eq!(rmq.try_range_minimum(..0), Err(RmqError::EmptyRange));
+        assert_eq!(
+            rmq.try_range_minimum((Bound::Excluded(1), Bound::Excluded(2))),
+            Err(RmqError::EmptyRange)
+        );
+        assert_eq!(
+            rmq.try_range_minimum(1..=3),
+            Err(RmqError::OutOfBounds { end: 3, len: 3 })
+        );
+        assert_eq!(rmq.try_range_minimum(..), Ok(1));
+        assert_eq!(rmq.range_minimum(..0), None);
+        assert_eq!(rmq.range_minimum(usize::MAX..), None);
+
+        let empty = super::Rmq::from_iter(core::iter::empty::<u32>());
+        assert!(empty.is_empty());
+        assert_eq!(empty.try_range_minimum(..), Err(RmqError::EmptyIndex));
+        assert_eq!(empty.range_minimum(0..=0), None);
+    }
+
+    proptest! {
+        #[test]
+        fn rmq_by_key_works(
+            elems in prop::collection::vec((any::<u16>(), any::<u8>()), 1..1000),
+            start in 0usize..1000,
+            len in 1usize..100
+        ) {
+            prop_assume!(start + len < elems.len());
+            let rmq = super::Rmq::from_iter_by_key(elems.iter(), |(_, b)| *b);
+            let rmq_by = super::Rmq::from_iter_by(elems.iter(), |x, y| x.1.cmp(&y.1));
+
+            let min = elems.iter().skip(start).take(len).map(|x| x.1).min().unwrap();
+            let min_pos = rmq.range_minimum(start..(start+len)).unwrap();
+            assert_eq!(elems[min_pos].1,min);
+            let min_pos = rmq_by.range_minimum(start..(start+len)).unwrap();
+            assert_eq!(elems[min_pos].1,min);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn rmq_f64_total_order(
+            elems in prop::collection::vec(any::<f64>(), 1..1000),
+            start in 0usize..1000,
+            len in 1usize..100
+        ) {
+            prop_assume!(start + len < elems.len());
+            let rmq = super::Rmq::from_f64_total_order(elems.iter().copied());
+
+            let min_pos = rmq.range_minimum(start..(start+len)).unwrap();
+
+            let min = elems.iter().skip(start).take(len).min_by(|a, b| a.total_cmp(b)).unwrap();
+            assert_eq!(elems[min_pos].total_cmp(min),core::cmp::Ordering::Equal);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn rmq_tie_break_with_duplicates(
+            elems in prop::collection::vec(0u8..4, 1..1000),
+            start in 0usize..1000,
+            len in 1usize..100
+        ) {
+            prop_assume!(start + len <= elems.len());
+            let leftmost = super::Rmq::from_iter_with_tie_break(elems.iter(), super::TieBreak::Leftmost);
+            let rightmost = super::Rmq::from_iter_with_tie_break(elems.iter(), super::TieBreak::Rightmost);
+            let default = super::Rmq::from_iter(elems.iter());
+
+            let range = start..(start+len);
+            let min = elems[range.clone()].iter().min().unwrap();
+            let first = range.clone().find(|&i| elems[i] == *min).unwrap();
+            let last = range.clone().rev().find(|&i| elems[i] == *min).unwrap();
+
+            assert_eq!(leftmost.range_minimum(range.clone()),Some(first));
+            assert_eq!(default.range_minimum(range.clone()),Some(first));
+            assert_eq!(rightmost.range_minimum(range),Some(last));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn exact_size_construction_matches_from_iter(
+            elems in prop::collection::vec(0u8..8, 0..3000),
+            rightmost in any::<bool>()
+        ) {
+            let tie_break = if rightmost { super::TieBreak::Rightmost } else { super::TieBreak::Leftmost };
+            let reversed = super::Rmq::from_iter_with_tie_break(elems.iter(), tie_break);
+            let backward: super::Rmq = super::Rmq {
+                cartesian_tree: super::CartesianTree::from_exact_size_iter_by(elems.iter(), Ord::cmp, tie_break),
+            };
+            prop_assert_eq!(backward.to_compact_bytes(), reversed.to_compact_bytes());
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn slice_construction_matches_from_iter(
+            elems in prop::collection::vec(prop::collection::vec(0u8..3, 0..3), 0..2000)
+        ) {
+            let expected = super::Rmq::from_iter(elems.iter().cloned()).to_compact_bytes();
+            prop_assert_eq!(super::Rmq::from_slice(&elems).to_compact_bytes(), expected.clone());
+            prop_assert_eq!(super::Rmq::from_iter_ref(&elems).to_compact_bytes(), expected);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn top_k_minima_match_sorted_range(
+            elems in prop::collection::vec(0u8..16, 1..500),
+            start in 0usize..500,
+            len in 1usize..100,
+            k in 0usize..120
+        ) {
+            prop_assume!(start + len <= elems.len());
+            let rmq: super::Rmq<super::layout::Compact> = super::Rmq::from_iter(elems.iter()).into_layout();
+            let actual: Vec<usize> = rmq.top_k_minima(start..start + len, k, &elems).collect();
+
+            let mut expected: Vec<usize> = (start..start + len).collect();
+            expected.sort_by_key(|&i| (elems[i], i));
+            expected.truncate(k);
+            prop_assert_eq!(actual, expected);
+
+            let rmq = super::Rmq::from_iter_with_tie_break(elems.iter(), super::TieBreak::Rightmost);
+            let actual: Vec<usize> = rmq.top_k_minima(start..start + len, k, &elems).collect();
+
+            let mut expected: Vec<usize> = (start..start + len).collect();
+            expected.sort_by_key(|&i| (elems[i], core::cmp::Reverse(i)));
+            expected.truncate(k);
+            prop_assert_eq!(actual, expected);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_checks_layout() {
+        use super::{layout, Rmq};
+
+        let rmq: Rmq<layout::Fast> = Rmq::from_iter(0..10_000u32).into_layout();
+        let json = serde_json::to_string(&rmq).unwrap();
+        let loaded: Rmq<layout::Fast> = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.range_minimum(100..), Some(100));
+
+        let err = serde_json::from_str::<Rmq>(&json).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("data was written in another layout"));
+    }
+
+    /// claims one more element than it yields
+    struct WrongLength(core::ops::Range<u8>);
+
+    impl Iterator for WrongLength {
+        type Item = u8;
+
+        fn next(&mut self) -> Option<u8> {
+            self.0.next()
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            let len = self.0.len() + 1;
+            (len, Some(len))
+        }
+    }
+
+    impl ExactSizeIterator for WrongLength {}
+
+    #[test]
+    #[should_panic(expected = "different number of elements than its length")]
+    fn exact_size_construction_checks_length() {
+        super::Rmq::from_exact_size_iter(WrongLength(0..100));
+    }
+
+    /// builds the Cartesian tree by recursively splitting at the reported minimum
+    fn naive_parents(elems: &[u8], tie_break: super::TieBreak) -> Vec<Option<usize>> {
+        fn split(
+            elems: &[u8],
+            range: core::ops::Range<usize>,
+            parent: Option<usize>,
+            tie_break: super::TieBreak,
+            parents: &mut Vec<Option<usize>>,
+        ) {
+            if range.is_empty() {
+                return;
+            }
+            let min = elems[range.clone()].iter().min().unwrap();
+            let pos = match tie_break {
+                super::TieBreak::Leftmost => range.clone().find(|&i| elems[i] == *min),
+                super::TieBreak::Rightmost => range.clone().rev().find(|&i| elems[i] == *min),
+            }
+            .unwrap();
+            parents[pos] = parent;
+            split(elems, range.start..pos, Some(pos), tie_break, parents);
+            split(elems, pos + 1..range.end, Some(pos), tie_break, parents);
+        }
+        let mut parents = vec![None; elems.len()];
+        split(elems, 0..elems.len(), None, tie_break, &mut parents);
+        parents
+    }
+
+    proptest! {
+        #[test]
+        fn cartesian_tree_navigation(
+            elems in prop::collection::vec(0u8..8, 1..300),
+            rightmost in any::<bool>()
+        ) {
+            let tie_break = if rightmost { super::TieBreak::Rightmost } else { super::TieBreak::Leftmost };
+            let rmq = super::Rmq::from_iter_with_tie_break(elems.iter(), tie_break);
+            let parents = naive_parents(&elems, tie_break);
+
+            for i in 0..elems.len() {
+                prop_assert_eq!(rmq.parent(i), parents[i]);
+                let children: Vec<usize> = (0..elems.len()).filter(|&c| parents[c] == Some(i)).collect();
+                prop_assert_eq!(rmq.left_child(i), children.iter().copied().find(|&c| c < i));
+                prop_assert_eq!(rmq.right_child(i), children.iter().copied().find(|&c| c > i));
+
+                let range = rmq.subtree_range(i).unwrap();
+                prop_assert_eq!(rmq.range_minimum(range.clone()), Some(i));
+                if *range.start() > 0 {
+                    prop_assert_ne!(rmq.range_minimum(range.start() - 1..=*range.end()), Some(i));
+                }
+                if *range.end() + 1 < elems.len() {
+                    prop_assert_ne!(rmq.range_minimum(*range.start()..=range.end() + 1), Some(i));
+                }
+            }
+            prop_assert_eq!(rmq.parent(elems.len()), None);
+            prop_assert_eq!(rmq.root(), parents.iter().position(|p| p.is_none()));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn nearest_smaller_values(elems in prop::collection::vec(0u8..8, 0..300)) {
+            let leftmost = super::Rmq::from_iter_with_tie_break(elems.iter(), super::TieBreak::Leftmost);
+            let rightmost = super::Rmq::from_iter_with_tie_break(elems.iter(), super::TieBreak::Rightmost);
+
+            for i in 0..elems.len() {
+                prop_assert_eq!(rightmost.previous_smaller(i), Ok((0..i).rev().find(|&j| elems[j] < elems[i])));
+                prop_assert_eq!(leftmost.previous_smaller_or_equal(i), Ok((0..i).rev().find(|&j| elems[j] <= elems[i])));
+                prop_assert_eq!(leftmost.next_smaller(i), Ok((i + 1..elems.len()).find(|&j| elems[j] < elems[i])));
+                prop_assert_eq!(rightmost.next_smaller_or_equal(i), Ok((i + 1..elems.len()).find(|&j| elems[j] <= elems[i])));
+            }
+            let out_of_bounds = if elems.is_empty() {
+                super::RmqError::EmptyIndex
+            } else {
+                super::RmqError::OutOfBounds { end: elems.len(), len: elems.len() }
+            };
+            prop_assert_eq!(leftmost.next_smaller(elems.len()), Err(out_of_bounds));
+            prop_assert_eq!(rightmost.previous_smaller(elems.len()), Err(out_of_bounds));
+
+            // the tie break survives serialization
+            let loaded = super::Rmq::from_compact_bytes(&rightmost.to_compact_bytes()).unwrap();
+            prop_assert_eq!(loaded.tie_break(), super::TieBreak::Rightmost);
+        }
+    }
+
+    #[test]
+    fn nearest_smaller_reports_wrong_tie_break() {
+        use super::{RmqError, TieBreak};
+
+        let leftmost = super::Rmq::from_iter_with_tie_break([1, 2], TieBreak::Leftmost);
+        let rightmost = super::Rmq::from_iter_with_tie_break([1, 2], TieBreak::Rightmost);
+        let needs_leftmost = Err(RmqError::WrongTieBreak {
+            required: TieBreak::Leftmost,
+        });
+        let needs_rightmost = Err(RmqError::WrongTieBreak {
+            required: TieBreak::Rightmost,
+        });
+        assert_eq!(leftmost.previous_smaller(1), needs_rightmost);
+        assert_eq!(leftmost.next_smaller_or_equal(0), needs_rightmost);
+        assert_eq!(rightmost.previous_smaller_or_equal(1), needs_leftmost);
+        assert_eq!(rightmost.next_smaller(0), needs_leftmost);
+    }
+
+    proptest! {
+        #[test]
+        fn range_minimum_batch_matches_single_queries(
+            elems in prop::collection::vec(0u8..16, 1..2000),
+            queries in prop::collection::vec((any::<prop::sample::Index>(), 0usize..100), 0..500)
+        ) {
+            let rmq = super::Rmq::from_iter(elems.iter());
+            let queries: Vec<_> = queries
+                .into_iter()
+                .map(|(start, len)| {
+                    let start = start.index(elems.len());
+                    start..(start + len + 1).min(elems.len())
+                })
+                .collect();
+            let mut out = vec![0; queries.len()];
+            rmq.range_minimum_batch(&queries, &mut out).unwrap();
+            for (query, pos) in queries.iter().zip(&out) {
+                prop_assert_eq!(rmq.range_minimum(query.clone()), Some(*pos));
+            }
+            #[cfg(feature = "rayon")]
+            {
+                let mut par_out = vec![0; queries.len()];
+                rmq.par_range_minimum_batch(&queries, &mut par_out).unwrap();
+                prop_assert_eq!(par_out, out);
+            }
+        }
+    }
+
+    #[test]
+    fn range_minimum_batch_reports_invalid_queries() {
+        let rmq = super::Rmq::from_iter([3, 1, 2]);
+        let mut out = [7; 2];
+        assert_eq!(
+            rmq.range_minimum_batch(&[0..2, 1..4], &mut out),
+            Err(super::RmqError::OutOfBounds { end: 3, len: 3 })
+        );
+        assert_eq!(out, [7; 2]);
+
+        // the invalid query is in another chunk than the first ones
+        #[cfg(feature = "rayon")]
+        {
+            let mut queries = vec![0..2; 50_000];
+            queries.push(2..2);
+            queries.push(1..4);
+            let mut out = vec![7; queries.len()];
+            assert_eq!(
+                rmq.par_range_minimum_batch(&queries, &mut out),
+                Err(super::RmqError::EmptyRange)
+            );
+            assert!(out.iter().all(|&pos| pos == 7));
+        }
+    }
 }
@@ -0,0 +1,200 @@
+use crate::cartesian_tree::CartesianTree;
+use crate::{RmqError, TieBreak};
+
+/// The RMQ data structure for range maximum queries
+///
+/// if the maximum of a range occurs more than once, the leftmost position is reported
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RangeMaxQuery {
+    cartesian_tree: CartesianTree,
+}
+
+impl RangeMaxQuery {
+    /// returns the position of the maximum element in `range`
+    ///
+    /// returns `None` if the range is empty or not contained in the index
+    pub fn range_maximum(&self, range: impl core::ops::RangeBounds<usize>) -> Option<usize> {
+        self.try_range_maximum(range).ok()
+    }
+
+    /// returns the position of the maximum element in `range`
+    /// or the reason why the range cannot be queried
+    pub fn try_range_maximum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        self.cartesian_tree.range_minimum(range)
+    }
+
+    /// returns the number of indexed elements
+    pub fn len(&self) -> usize {
+        self.cartesian_tree.len()
+    }
+
+    /// returns `true` if the index does not contain any elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Ord> FromIterator<T> for RangeMaxQuery {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self {
+            cartesian_tree: CartesianTree::from_iter_by(iter, |a, b| b.cmp(a), TieBreak::Leftmost),
+        }
+    }
+}
+
+/// Answers both range minimum and range maximum queries
+///
+/// Both Cartesian trees are built in a single pass over the input.
+///
+/// if the minimum or the maximum of a range occurs more than once,
+/// the leftmost position is reported
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MinMaxRmq {
+    min_tree: CartesianTree,
+    max_tree: CartesianTree,
+}
+
+impl MinMaxRmq {
+    /// returns the position of the minimum element in `range`
+    ///
+    /// returns `None` if the range is empty or not contained in the index
+    pub fn range_minimum(&self, range: impl core::ops::RangeBounds<usize>) -> Option<usize> {
+        self.try_range_minimum(range).ok()
+    }
+
+    /// returns the position of the minimum element in `range`
+    /// or the reason why the range cannot be queried
+    pub fn try_range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        self.min_tree.range_minimum(range)
+    }
+
+    /// returns the position of the maximum element in `range`
+    ///
+    /// returns `None` if the range is empty or not contained in the index
+    pub fn range_maximum(&self, range: impl core::ops::RangeBounds<usize>) -> Option<usize> {
+        self.try_range_maximum(range).ok()
+    }
+
+    /// returns the position of the maximum element in `range`
+    /// or the reason why the range cannot be queried
+    pub fn try_range_maximum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        self.max_tree.range_minimum(range)
+    }
+
+    /// returns the positions of the minimum and the maximum element in `range`
+    ///
+    /// returns `None` if the range is empty or not contained in the index
+    pub fn range_min_max(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Option<(usize, usize)> {
+        self.try_range_min_max(range).ok()
+    }
+
+    /// returns the positions of the minimum and the maximum element in `range`
+    /// or the reason why the range cannot be queried
+    pub fn try_range_min_max(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<(usize, usize), RmqError> {
+        let range = self.min_tree.resolve_range(range)?;
+        let min = self.min_tree.range_minimum(range.clone())?;
+        let max = self.max_tree.range_minimum(range)?;
+        Ok((min, max))
+    }
+
+    /// returns the number of indexed elements
+    pub fn len(&self) -> usize {
+        self.min_tree.len()
+    }
+
+    /// returns `true` if the index does not contain any elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Ord + Clone> FromIterator<T> for MinMaxRmq {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut min_builder = CartesianTree::builder();
+        let mut max_builder = CartesianTree::builder();
+        for item in iter {
+            min_builder.push(item.clone());
+            max_builder.push_by(item, |a: &T, b: &T| b.cmp(a));
+        }
+        Self {
+            min_tree: min_builder.build(),
+            max_tree: max_builder.build(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn range_maximum_works(
+            elems in prop::collection::vec(any::<u16>(), 1..1000),
+            start in 0usize..1000,
+            len in 1usize..100
+        ) {
+            prop_assume!(start + len < elems.len());
+            let rmq = super::RangeMaxQuery::from_iter(elems.iter());
+
+            let max_pos = rmq.range_maximum(start..(start+len)).unwrap();
+
+            let max = elems.iter().skip(start).take(len).copied().max().unwrap();
+            assert_eq!(elems[max_pos],max);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn range_min_max_works(
+            elems in prop::collection::vec(any::<u16>(), 1..1000),
+            start in 0usize..1000,
+            len in 1usize..100
+        ) {
+            prop_assume!(start + len < elems.len());
+            let rmq = super::MinMaxRmq::from_iter(elems.iter());
+
+            let (min_pos, max_pos) = rmq.range_min_max(start..(start+len)).unwrap();
+
+            let min = elems.iter().skip(start).take(len).copied().min().unwrap();
+            let max = elems.iter().skip(start).take(len).copied().max().unwrap();
+            assert_eq!(elems[min_pos],min);
+            assert_eq!(elems[max_pos],max);
+            assert_eq!(rmq.range_minimum(start..(start+len)),Some(min_pos));
+            assert_eq!(rmq.range_maximum(start..(start+len)),Some(max_pos));
+        }
+    }
+
+    #[test]
+    fn min_max_report_leftmost_ties_and_errors() {
+        let rmq = super::MinMaxRmq::from_iter([2, 1, 3, 1, 3]);
+        assert_eq!(rmq.try_range_minimum(..), Ok(1));
+        assert_eq!(rmq.try_range_maximum(..), Ok(2));
+        assert_eq!(rmq.range_min_max(2..), Some((3, 2)));
+        assert_eq!(
+            rmq.try_range_minimum(3..3),
+            Err(crate::RmqError::EmptyRange)
+        );
+        assert_eq!(
+            rmq.try_range_maximum(..6),
+            Err(crate::RmqError::OutOfBounds { end: 5, len: 5 })
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use crate::format::RmqView;
+use crate::layout::Layout;
+use crate::{Rmq, RmqError};
+
+/// Indexes answering range minimum queries by position
+///
+/// Code written against the trait works with every implementation,
+/// which trade space for query time:
+///
+/// - [`Rmq`] uses about `2.7` bits per element and does not keep the values
+/// - [`BlockRmq`](crate::BlockRmq) keeps the values and one word per element
+/// - [`SparseTableRmq`](crate::SparseTableRmq) keeps the values and
+///   `log n` words per element for queries with a single comparison
+///
+/// ```rust
+/// use range_minimum_query::{BlockRmq, RangeMinimum, Rmq, SparseTableRmq};
+///
+/// fn minimum_of_halves(rmq: &impl RangeMinimum) -> Option<(usize, usize)> {
+///     let mid = rmq.len() / 2;
+///     Some((rmq.range_minimum(..mid)?, rmq.range_minimum(mid..)?))
+/// }
+///
+/// let a = [3, 1, 4, 1, 5, 9, 2, 6];
+/// assert_eq!(minimum_of_halves(&Rmq::from_iter(a)), Some((1, 6)));
+/// assert_eq!(minimum_of_halves(&SparseTableRmq::new(a.to_vec())), Some((1, 6)));
+/// assert_eq!(minimum_of_halves(&BlockRmq::new(a.to_vec())), Some((1, 6)));
+/// ```
+pub trait RangeMinimum {
+    /// returns the number of indexed elements
+    fn len(&self) -> usize;
+
+    /// returns `true` if the index does not contain any elements
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// returns the position of the minimum element in `range`
+    /// or the reason why the range cannot be queried
+    ///
+    /// if the minimum occurs more than once, the leftmost position is reported
+    /// unless the index was built with [`TieBreak::Rightmost`](crate::TieBreak)
+    fn try_range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError>;
+
+    /// returns the position of the minimum element in `range`
+    ///
+    /// returns `None` if the range is empty or not contained in the index
+    fn range_minimum(&self, range: impl core::ops::RangeBounds<usize>) -> Option<usize> {
+        self.try_range_minimum(range).ok()
+    }
+}
+
+impl<L: Layout> RangeMinimum for Rmq<L> {
+    fn len(&self) -> usize {
+        Rmq::len(self)
+    }
+
+    fn try_range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        Rmq::try_range_minimum(self, range)
+    }
+}
+
+impl<L: Layout> RangeMinimum for RmqView<'_, L> {
+    fn len(&self) -> usize {
+        RmqView::len(self)
+    }
+
+    fn try_range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        RmqView::try_range_minimum(self, range)
+    }
+}
+
+/// converts `range` into the inclusive range of positions it covers
+/// in an index of `length` elements
+pub(crate) fn resolve_range(
+    range: impl core::ops::RangeBounds<usize>,
+    length: usize,
+) -> Result<core::ops::RangeInclusive<usize>, RmqError> {
+    if length == 0 {
+        return Err(RmqError::EmptyIndex);
+    }
+    let range_start = match range.start_bound() {
+        core::ops::Bound::Included(t) => *t,
+        core::ops::Bound::Excluded(t) => t.checked_add(1).ok_or(RmqError::EmptyRange)?,
+        core::ops::Bound::Unbounded => 0,
+    };
+    let range_end = match range.end_bound() {
+        core::ops::Bound::Included(t) => *t,
+        core::ops::Bound::Excluded(t) => t.checked_sub(1).ok_or(RmqError::EmptyRange)?,
+        core::ops::Bound::Unbounded => length - 1,
+    };
+    if range_start > range_end {
+        return Err(RmqError::EmptyRange);
+    }
+    if range_end >= length {
+        return Err(RmqError::OutOfBounds {
+            end: range_end,
+            len: length,
+        });
+    }
+    Ok(range_start..=range_end)
+}
@@ -0,0 +1,181 @@
+use alloc::vec::Vec;
+
+use crate::range_minimum::{resolve_range, RangeMinimum};
+use crate::RmqError;
+
+/// The positions of the minima of all windows whose length is a power of two
+///
+/// Level `k` stores the minimum of the `2^k` candidates starting at every index,
+/// the candidates of level 0 are given on construction. Positions are compared
+/// by `less`, which has to report if the value at the first one is smaller.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct MinTable {
+    levels: Vec<Vec<usize>>,
+}
+
+impl MinTable {
+    pub(crate) fn new(candidates: Vec<usize>, less: impl Fn(usize, usize) -> bool) -> Self {
+        let n = candidates.len();
+        let mut levels = alloc::vec![candidates];
+        while 1 << levels.len() <= n {
+            let half = 1 << (levels.len() - 1);
+            let prev = levels.last().unwrap();
+            let level = (0..=n - 2 * half)
+                .map(|i| {
+                    let (a, b) = (prev[i], prev[i + half]);
+                    if less(b, a) {
+                        b
+                    } else {
+                        a
+                    }
+                })
+                .collect();
+            levels.push(level);
+        }
+        Self { levels }
+    }
+
+    /// returns the minimum of the candidates `start..=end`, the leftmost one on ties
+    pub(crate) fn query(
+        &self,
+        start: usize,
+        end: usize,
+        less: impl Fn(usize, usize) -> bool,
+    ) -> usize {
+        let level = (end - start + 1).ilog2() as usize;
+        let a = self.levels[level][start];
+        let b = self.levels[level][end + 1 - (1 << level)];
+        if less(b, a) {
+            b
+        } else {
+            a
+        }
+    }
+
+    pub(crate) fn heap_size_in_bytes(&self) -> usize {
+        self.levels
+            .iter()
+            .map(|level| level.capacity() * core::mem::size_of::<usize>())
+            .sum()
+    }
+}
+
+/// A sparse table answering range minimum queries with a single comparison
+///
+/// The positions of the minima of all ranges whose length is a power of two are
+/// precomputed, which takes `n log n` words for `n` elements. Every query covers
+/// its range with two of them. This is faster than [`Rmq`](crate::Rmq) for small
+/// inputs where the space does not matter.
+///
+/// If the minimum of a range occurs more than once, the leftmost position is reported.
+///
+/// ```rust
+/// use range_minimum_query::SparseTableRmq;
+///
+/// let rmq = SparseTableRmq::new(vec![0,5,2,5,4,3,1,6,3]);
+/// assert_eq!(rmq.range_minimum(2..=7),Some(6));
+/// assert_eq!(rmq.values()[6],1);
+/// ```
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SparseTableRmq<T> {
+    values: Vec<T>,
+    table: MinTable,
+}
+
+impl<T: Ord> SparseTableRmq<T> {
+    /// builds the table over `values` and takes ownership of them
+    pub fn new(values: Vec<T>) -> Self {
+        let table = MinTable::new((0..values.len()).collect(), |a, b| values[a] < values[b]);
+        Self { values, table }
+    }
+
+    /// returns the position of the minimum element in `range`
+    ///
+    /// returns `None` if the range is empty or not contained in the table
+    pub fn range_minimum(&self, range: impl core::ops::RangeBounds<usize>) -> Option<usize> {
+        self.try_range_minimum(range).ok()
+    }
+
+    /// returns the position of the minimum element in `range`
+    /// or the reason why the range cannot be queried
+    pub fn try_range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        let range = resolve_range(range, self.len())?;
+        Ok(self.table.query(*range.start(), *range.end(), |a, b| {
+            self.values[a] < self.values[b]
+        }))
+    }
+}
+
+impl<T> SparseTableRmq<T> {
+    /// returns the indexed values
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// returns the number of indexed values
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// returns `true` if no values are indexed
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// returns the heap memory used by the table in bytes, without the values
+    pub fn heap_size_in_bytes(&self) -> usize {
+        self.table.heap_size_in_bytes()
+    }
+
+    /// consumes the table and returns the indexed values
+    pub fn into_values(self) -> Vec<T> {
+        self.values
+    }
+}
+
+impl<T: Ord> FromIterator<T> for SparseTableRmq<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+impl<T: Ord> RangeMinimum for SparseTableRmq<T> {
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn try_range_minimum(
+        &self,
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Result<usize, RmqError> {
+        SparseTableRmq::try_range_minimum(self, range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::SparseTableRmq;
+
+    proptest! {
+        #[test]
+        fn sparse_table_matches_scan(
+            elems in prop::collection::vec(0u8..16, 1..1000),
+            start in any::<prop::sample::Index>(),
+            end in any::<prop::sample::Index>()
+        ) {
+            let rmq = SparseTableRmq::new(elems.clone());
+            let (start, end) = (start.index(elems.len()), end.index(elems.len()));
+            let range = start.min(end)..=start.max(end);
+
+            let expected = range.clone().min_by_key(|&i| (elems[i], i));
+            prop_assert_eq!(rmq.range_minimum(range), expected);
+        }
+    }
+}
@@ -1,3 +1,6 @@
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU8, Ordering};
+
 pub const ONES_STEP_9: u64 = 1 << 0 | 1 << 9 | 1 << 18 | 1 << 27 | 1 << 36 | 1 << 45 | 1 << 54;
 
 pub const INV_COUNT_STEP_9: u64 = 1 << 54 | 2 << 45 | 3 << 36 | 4 << 27 | 5 << 18 | 6 << 9 | 7;
@@ -143,7 +146,11 @@ const LT_SEL: [u8; 2048] = [
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7,
 ];
 
-#[cfg(target_feature = "bmi2")]
+/// # Safety
+///
+/// the CPU has to support BMI2
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
 pub unsafe fn select1_in_u64_bmi2(a: u64, k: u64) -> u64 {
     let i = 1 << k;
     let y = core::arch::x86_64::_pdep_u64(i, a);
@@ -212,14 +219,140 @@ pub const fn select1_in_u64_slow(x: u64, mut i: u64) -> u64 {
     }
 }
 
-pub fn select1_in_u64(a: u64, k: u64) -> u64 {
-    #[cfg(target_feature = "bmi2")]
-    {
-        unsafe { select1_in_u64_bmi2(a, k) }
+/// Implementations of a primitive from which the running CPU picks one
+pub(crate) trait Backend: Copy + PartialEq + core::fmt::Debug + 'static {
+    /// all implementations, the cell stores a backend as its position plus one
+    const ALL: &'static [Self];
+
+    fn detect() -> Self;
+
+    fn is_supported(self) -> bool;
+}
+
+/// The backend of a primitive, detected on first use unless one was forced
+pub(crate) struct BackendCell<B> {
+    id: AtomicU8,
+    backend: PhantomData<B>,
+}
+
+impl<B: Backend> BackendCell<B> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            id: AtomicU8::new(0),
+            backend: PhantomData,
+        }
     }
-    #[cfg(not(target_feature = "bmi2"))]
-    {
-        select1_in_u64_slow(a, k)
+
+    #[inline]
+    pub(crate) fn load(&self) -> B {
+        match self.id.load(Ordering::Relaxed) {
+            0 => {
+                let backend = B::detect();
+                self.store(backend);
+                backend
+            }
+            id => B::ALL[id as usize - 1],
+        }
+    }
+
+    /// # Panics
+    ///
+    /// if the running CPU does not support the backend
+    pub(crate) fn force(&self, backend: B) {
+        assert!(
+            backend.is_supported(),
+            "the CPU does not support the {backend:?} backend"
+        );
+        self.store(backend);
+    }
+
+    fn store(&self, backend: B) {
+        let idx = B::ALL.iter().position(|&b| b == backend).unwrap();
+        self.id.store(idx as u8 + 1, Ordering::Relaxed);
+    }
+}
+
+/// Implementations of selecting the `k`-th one bit in a word
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SelectBackend {
+    /// portable broadword selection with lookup tables
+    Broadword,
+    /// `PDEP` and `TZCNT` instructions of x86-64 CPUs supporting BMI2
+    Bmi2,
+}
+
+static SELECT_BACKEND: BackendCell<SelectBackend> = BackendCell::new();
+
+impl SelectBackend {
+    /// returns `Bmi2` if the running CPU supports it and `Broadword` otherwise
+    ///
+    /// without the `std` feature BMI2 is only used if it is enabled at compile time
+    pub fn detect() -> Self {
+        if Self::Bmi2.is_supported() {
+            Self::Bmi2
+        } else {
+            Self::Broadword
+        }
+    }
+
+    /// returns `true` if the running CPU has the instructions of the backend
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::Broadword => true,
+            Self::Bmi2 => bmi2_supported(),
+        }
+    }
+
+    /// returns the backend that selects the bits in all indexes
+    pub fn current() -> Self {
+        SELECT_BACKEND.load()
+    }
+
+    /// selects with this backend from now on, e.g. to benchmark
+    /// `Broadword` on a CPU that supports BMI2
+    ///
+    /// # Panics
+    ///
+    /// if the running CPU does not support the backend
+    pub fn force(self) {
+        SELECT_BACKEND.force(self);
+    }
+}
+
+impl Backend for SelectBackend {
+    const ALL: &'static [Self] = &[Self::Broadword, Self::Bmi2];
+
+    fn detect() -> Self {
+        Self::detect()
+    }
+
+    fn is_supported(self) -> bool {
+        Self::is_supported(self)
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+fn bmi2_supported() -> bool {
+    std::is_x86_feature_detected!("bmi2")
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "std")))]
+fn bmi2_supported() -> bool {
+    cfg!(target_feature = "bmi2")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn bmi2_supported() -> bool {
+    false
+}
+
+#[inline]
+pub fn select1_in_u64(a: u64, k: u64) -> u64 {
+    match SELECT_BACKEND.load() {
+        // SAFETY: the backend is only selected if the CPU supports BMI2
+        #[cfg(target_arch = "x86_64")]
+        SelectBackend::Bmi2 => unsafe { select1_in_u64_bmi2(a, k) },
+        _ => select1_in_u64_slow(a, k),
     }
 }
 
@@ -241,8 +374,11 @@ mod tests {
 
     proptest! {
         #[test]
-        #[cfg(target_feature = "bmi2")]
+        #[cfg(target_arch = "x86_64")]
         fn select1_in_u64_slow_eq_fast(data: u64) {
+            if !super::SelectBackend::Bmi2.is_supported() {
+                return Ok(());
+            }
             let bv = BitVec::<_, Lsb0>::from_element(data);
             for (found, _one_pos) in bv.iter_ones().enumerate() {
                 let comp_pos_fast = unsafe { super::select1_in_u64_bmi2(data,found as u64) };
@@ -251,4 +387,18 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn backend_cell_detects_once_and_forces() {
+        use super::{BackendCell, SelectBackend};
+
+        let cell = BackendCell::<SelectBackend>::new();
+        assert_eq!(cell.load(), SelectBackend::detect());
+        for backend in [SelectBackend::Broadword, SelectBackend::Bmi2] {
+            if backend.is_supported() {
+                cell.force(backend);
+                assert_eq!(cell.load(), backend);
+            }
+        }
+    }
 }
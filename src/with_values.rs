@@ -0,0 +1,187 @@
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use crate::layout::{self, Layout};
+use crate::{Rmq, TieBreak};
+
+/// An [`Rmq`] index stored together with the values it was built from,
+/// so queries can return the minimum values and not just their positions.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RmqWithValues<T> {
+    rmq: Rmq,
+    values: Vec<T>,
+}
+
+impl<T: Ord> RmqWithValues<T> {
+    /// builds the index over `values` and takes ownership of them
+    pub fn new(values: Vec<T>) -> Self {
+        let rmq = Rmq::from_iter(values.iter());
+        Self { rmq, values }
+    }
+
+    /// returns the position of the minimum element in `range`
+    pub fn range_minimum(&self, range: impl core::ops::RangeBounds<usize>) -> Option<usize> {
+        self.rmq.range_minimum(range)
+    }
+
+    /// returns the minimum value in `range`
+    pub fn min_value(&self, range: impl core::ops::RangeBounds<usize>) -> Option<&T> {
+        self.min_entry(range).map(|(_, value)| value)
+    }
+
+    /// returns the position and the value of the minimum element in `range`
+    pub fn min_entry(&self, range: impl core::ops::RangeBounds<usize>) -> Option<(usize, &T)> {
+        self.range_minimum(range)
+            .map(|pos| (pos, &self.values[pos]))
+    }
+
+    /// iterates over all elements in `range` in non-decreasing order of their values,
+    /// equal values are returned in order of their positions
+    ///
+    /// the iterator is empty if the range cannot be queried
+    pub fn iter_minima(&self, range: impl core::ops::RangeBounds<usize>) -> Minima<'_, T> {
+        Minima::new(&self.rmq, &self.values, range)
+    }
+}
+
+impl<T> RmqWithValues<T> {
+    /// returns the underlying position-only index
+    pub fn rmq(&self) -> &Rmq {
+        &self.rmq
+    }
+
+    /// returns the indexed values
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// returns the number of indexed values
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// returns `true` if no values are indexed
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// consumes the index and returns the indexed values
+    pub fn into_values(self) -> Vec<T> {
+        self.values
+    }
+}
+
+impl<T: Ord> FromIterator<T> for RmqWithValues<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+/// Iterator over the elements of a range in order of minimality,
+/// created by [`RmqWithValues::iter_minima`] and [`Rmq::top_k_minima`].
+///
+/// Each step answers one range minimum query and splits the
+/// remaining range around the reported position. Equal values are returned
+/// in the order the tie break of the index reports them: from left to right
+/// for [`TieBreak::Leftmost`] and from right to left for [`TieBreak::Rightmost`].
+#[derive(Debug)]
+pub struct Minima<'a, T, L = layout::Balanced> {
+    rmq: &'a Rmq<L>,
+    values: &'a [T],
+    /// the minimum of every subrange, keyed by its value and its position
+    /// in the order of the tie break
+    heap: BinaryHeap<Reverse<(&'a T, usize, usize, usize)>>,
+}
+
+impl<'a, T: Ord, L: Layout> Minima<'a, T, L> {
+    pub(crate) fn new(
+        rmq: &'a Rmq<L>,
+        values: &'a [T],
+        range: impl core::ops::RangeBounds<usize>,
+    ) -> Self {
+        let mut minima = Self {
+            rmq,
+            values,
+            heap: BinaryHeap::new(),
+        };
+        if let Ok(range) = rmq.cartesian_tree.resolve_range(range) {
+            minima.push_range(*range.start(), range.end() + 1);
+        }
+        minima
+    }
+
+    /// maps positions to the order of the tie break and back
+    fn order(&self, pos: usize) -> usize {
+        match self.rmq.tie_break() {
+            TieBreak::Leftmost => pos,
+            TieBreak::Rightmost => !pos,
+        }
+    }
+
+    fn push_range(&mut self, start: usize, end: usize) {
+        if start < end {
+            if let Some(pos) = self.rmq.range_minimum(start..end) {
+                self.heap
+                    .push(Reverse((&self.values[pos], self.order(pos), start, end)));
+            }
+        }
+    }
+}
+
+impl<'a, T: Ord, L: Layout> Iterator for Minima<'a, T, L> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((value, order, start, end)) = self.heap.pop()?;
+        let pos = self.order(order);
+        self.push_range(start, pos);
+        self.push_range(pos + 1, end);
+        Some((pos, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::RmqWithValues;
+
+    proptest! {
+        #[test]
+        fn min_entry_matches_scan(
+            elems in prop::collection::vec(any::<u16>(), 1..1000),
+            start in 0usize..1000,
+            len in 1usize..100
+        ) {
+            prop_assume!(start + len < elems.len());
+            let rmq = RmqWithValues::new(elems.clone());
+
+            let (pos, value) = rmq.min_entry(start..(start+len)).unwrap();
+
+            let min = elems.iter().skip(start).take(len).min().unwrap();
+            assert_eq!(value,min);
+            assert_eq!(&elems[pos],min);
+            assert_eq!(rmq.min_value(start..(start+len)),Some(min));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn iter_minima_is_sorted(
+            elems in prop::collection::vec(0u8..16, 1..500),
+            start in 0usize..500,
+            len in 1usize..100
+        ) {
+            prop_assume!(start + len <= elems.len());
+            let rmq = RmqWithValues::new(elems.clone());
+
+            let actual: Vec<(usize, &u8)> = rmq.iter_minima(start..(start+len)).collect();
+
+            let mut expected: Vec<(usize, &u8)> = (start..start+len).map(|i| (i, &elems[i])).collect();
+            expected.sort_by_key(|&(i, v)| (v, i));
+            assert_eq!(actual,expected);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Uses the crate from a `#![no_std]` crate, the standard library is
+//! only linked for the test harness. Run with `--no-default-features`
+//! to test the crate without `std`, CI also builds it for a target
+//! without the standard library.
+#![no_std]
+
+extern crate alloc;
+extern crate std;
+
+use alloc::vec::Vec;
+
+use range_minimum_query::{Rmq, RmqBuilder, RmqError, RmqView, RmqWithValues};
+
+#[test]
+fn queries_without_std() {
+    let values: Vec<u32> = (0..1000).map(|i| (i * 7919) % 1009).collect();
+    let rmq: Rmq = values.iter().collect();
+    for start in (0..values.len()).step_by(37) {
+        for end in (start..values.len()).step_by(53) {
+            let pos = rmq.range_minimum(start..=end).unwrap();
+            let min = values[start..=end].iter().min().unwrap();
+            assert_eq!(values[pos], *min);
+        }
+    }
+    assert_eq!(
+        rmq.try_range_minimum(0..2000),
+        Err(RmqError::OutOfBounds {
+            end: 1999,
+            len: 1000
+        })
+    );
+}
+
+#[test]
+fn compact_bytes_without_std() {
+    let mut builder = RmqBuilder::new();
+    builder.extend([4, 2, 7, 1, 9]);
+    let rmq = builder.finish();
+    let loaded = Rmq::from_compact_bytes(&rmq.to_compact_bytes()).unwrap();
+    assert_eq!(loaded.range_minimum(0..3), Some(1));
+    assert!(RmqView::new(&[]).is_err());
+
+    let with_values = RmqWithValues::new(alloc::vec![3, 1, 2]);
+    assert_eq!(with_values.min_value(..), Some(&1));
+}